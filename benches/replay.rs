@@ -0,0 +1,102 @@
+//! Repeatable replay benchmarks over a generated fixture, with no extra
+//! crates: `cargo bench`. Reports the best of several runs per case in
+//! msgs/s and ns/message. `src/bin/bench.rs` stays for large real files.
+//!
+//! `cargo bench --bench replay -- --write <path> [messages]` writes the
+//! same fixture at any size instead, for `bench` runs others can repeat;
+//! the README's 20 M‑message figures come from `--write <path> 20000000`.
+
+use std::{hint::black_box, time::{Duration, Instant}};
+
+use faststorage_native::*;
+use faststorage_native::orderbook::DepthBook;
+
+const MESSAGES: usize = 500_000;
+const RUNS:     usize = 10;
+
+/// Deterministic mix of 90 % depth updates over 200 price levels and 10 %
+/// trades, in 64 KiB LZ4 blocks — the shape of the captures `bench` sees.
+/// The xorshift seed is fixed, so a given `messages` always gives the
+/// same bytes.
+fn write_fixture(path: &str, messages: usize) {
+    let mut w = FastCacheWriter::create(path, 64 * 1024, CompressionMode::Lz4).unwrap();
+    let mut x = 0x2545_f491_4f6c_dd1du64;
+    for i in 0..messages as i64 {
+        x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+        let price = 100_000_000 * (10_000 + (x % 200) as i64);
+        let side  = 1 + ((x >> 4) % 2) as u8;
+        let msg = if x.is_multiple_of(10) {
+            Message::Tick(TickItem::new(i, i, price, (x >> 8) as i64 % 1_000_000, side))
+        } else {
+            let volume = if x.is_multiple_of(7) { 0 } else { (x >> 8) as i64 % 1_000_000 };
+            Message::Depth(DepthItem::new(i, price, volume, side | MarketFlag::END_OF_TX.bits()))
+        };
+        w.write_typed(&msg).unwrap();
+    }
+    w.finish().unwrap();
+}
+
+fn fixture() -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!("faststorage-replay-{}.bin", std::process::id()));
+    let path = path.to_str().expect("temp path is UTF-8");
+    write_fixture(path, MESSAGES);
+    let data = std::fs::read(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    data
+}
+
+/// Compressed payloads of every block, split out of the file framing.
+fn frames(data: &[u8]) -> Vec<&[u8]> {
+    let (mut at, mut out) = (4, Vec::new());
+    while at < data.len() {
+        let len = i32::from_le_bytes(data[at..at + 4].try_into().unwrap()) as usize;
+        out.push(&data[at + 4..at + 4 + len]);
+        at += 4 + len;
+    }
+    out
+}
+
+fn bench(name: &str, msgs: usize, mut f: impl FnMut()) {
+    f();    // warm‑up
+    let best = (0..RUNS).map(|_| { let t = Instant::now(); f(); t.elapsed() }).min().unwrap_or(Duration::ZERO);
+    let secs = best.as_secs_f64();
+    println!("{name:<12} {:>12.0} msgs/s {:>8.2} ns/msg", msgs as f64 / secs, secs * 1e9 / msgs as f64);
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; anything else is ours.
+    let args: Vec<String> = std::env::args().skip(1).filter(|a| a != "--bench").collect();
+    if let [flag, path, rest @ ..] = &args[..] {
+        if flag == "--write" {
+            let messages = rest.first().map_or(MESSAGES, |n| n.parse().expect("messages must be a number"));
+            write_fixture(path, messages);
+            println!("wrote {messages} messages to {path}");
+            return;
+        }
+    }
+
+    let data = fixture();
+    let frames = frames(&data);
+    println!("fixture: {MESSAGES} messages in {} blocks, {} bytes", frames.len(), data.len());
+
+    let mut out = Vec::new();
+    bench("unpickle", MESSAGES, || {
+        for f in &frames { black_box(k4os_pickler::unpickle_into(f, &mut out).unwrap()); }
+    });
+
+    bench("iterate", MESSAGES, || {
+        let mut msgs = FastCacheReader::from_bytes(data.clone()).unwrap().messages();
+        let mut n = 0;
+        while let Some(m) = msgs.next_message().unwrap() { n += m.size() as usize; }
+        black_box(n);
+    });
+
+    bench("order book", MESSAGES, || {
+        let mut msgs = FastCacheReader::from_bytes(data.clone()).unwrap().messages();
+        let mut book = DepthBook::default();
+        while let Some(m) = msgs.next_message().unwrap() {
+            if let Some(d) = m.as_depth() { book.apply(&d); }
+        }
+        black_box(book.best_bid());
+    });
+}
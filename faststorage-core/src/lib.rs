@@ -0,0 +1,1297 @@
+//! FastStorage core: the block and message decoding of
+//! `faststorage_native`, without the filesystem layer. It builds under
+//! `no_std` with `alloc`; the `std` feature adds `FastStorageError::Io`,
+//! `std::error::Error`‑based interop and `MessageHeader::to_system_time`.
+//!
+//! Available without `std`:
+//!
+//! - `k4os_pickler`: `pickle`, `pickle_with`, `unpickle`, `unpickle_into`,
+//!   `unpickled_len`, and `crc32::checksum` for block checksums;
+//! - the wire structs (`MessageHeader`, `DepthItem`, `TickItem`,
+//!   `CandleItem`, `SymbolItem`, `Message`) with their getters, `Scale`
+//!   and `MessageKind::wire_size`;
+//! - `MessageRef` and its `decode`/`as_*` accessors, `DecodedBlock`
+//!   and `BlockMessages` for walking one decompressed block, and
+//!   `ChunkedBlock` for walking one pickled block without decoding it whole;
+//! - `SliceReader::from_bytes`, the whole‑file entry point over a `&[u8]`;
+//! - `FastStorageError`, `Result` and the `ERR_*` codes.
+//!
+//! Files, streams, mmap, the C ABI, the order book and aggregation stay
+//! in `faststorage_native`, which re‑exports everything here.
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{mem::size_of, ops::Range, ptr};
+
+pub use k4os_pickler::CompressionMode;
+use bitflags::bitflags;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+// Fails with [`FastStorageError::Invalid`] unless `cond` holds.
+macro_rules! ensure {
+    ($cond:expr, $($fmt:tt)+) => {
+        if !$cond { return Err(FastStorageError::Invalid(format!($($fmt)+))); }
+    };
+}
+
+/* ────────────────  1. decoder  ────────────── */
+
+pub mod k4os_pickler {
+    use super::*;
+    use lz4_flex::block;
+
+    /// How [`pickle_with`] encodes a block. Both produce the same framing,
+    /// so any reader handles either.
+    #[repr(i32)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum CompressionMode {
+        /// Copy the bytes as a diff‑0 block; no CPU spent on LZ4.
+        Stored = 0,
+        /// LZ4‑compress, falling back to stored when it doesn't help.
+        #[default]
+        Lz4    = 1,
+    }
+
+    /// Inverse of [`unpickle`]. Emits a stored block (diff 0) when LZ4
+    /// doesn't make the data smaller.
+    pub fn pickle(src: &[u8]) -> Result<Vec<u8>> { pickle_with(src, CompressionMode::Lz4) }
+
+    pub fn pickle_with(src: &[u8], mode: CompressionMode) -> Result<Vec<u8>> {
+        if src.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let compressed = match mode {
+            CompressionMode::Stored => Vec::new(),
+            CompressionMode::Lz4    => block::compress(src),
+        };
+        if mode == CompressionMode::Stored || compressed.len() >= src.len() {
+            let mut out = Vec::with_capacity(1 + src.len());
+            out.push(0);
+            out.extend_from_slice(src);
+            return Ok(out);
+        }
+
+        let diff = src.len() - compressed.len();
+        ensure!(diff <= u32::MAX as usize, "block too large to pickle");
+        let (code, diff_len) = match diff { 0..=0xff => (1u8, 1), 0x100..=0xffff => (2, 2), _ => (3, 4) };
+
+        let mut out = Vec::with_capacity(1 + diff_len + compressed.len());
+        out.push(code << 6);
+        out.extend_from_slice(&(diff as u32).to_le_bytes()[..diff_len]);
+        out.extend_from_slice(&compressed);
+        Ok(out)
+    }
+
+    /// A block whose header names a pickle version other than 0, the only
+    /// one this crate decodes. Reaches callers as
+    /// [`FastStorageError::UnsupportedVersion`].
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct UnsupportedVersion(pub u8);
+
+    impl core::fmt::Display for UnsupportedVersion {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "unsupported pickle version {}", self.0)
+        }
+    }
+
+    impl core::error::Error for UnsupportedVersion {}
+
+    /// A compressed block that decoded to a different length than its
+    /// header's `diff` field promised, i.e. a corrupt header or payload.
+    /// When the block overruns, decoding stops there and `got` is only a
+    /// lower bound.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct DecompressedSizeMismatch { pub expected: usize, pub got: usize }
+
+    impl core::fmt::Display for DecompressedSizeMismatch {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "block decompressed to {} bytes, header says {}", self.got, self.expected)
+        }
+    }
+
+    impl core::error::Error for DecompressedSizeMismatch {}
+
+    fn size_error(e: block::DecompressError) -> FastStorageError {
+        match e {
+            block::DecompressError::OutputTooSmall { expected, actual } =>
+                DecompressedSizeMismatch { expected: actual, got: expected }.into(),
+            e => e.into(),
+        }
+    }
+
+    /// Fails with [`UnsupportedVersion`] unless the version bits are 0, and with
+    /// [`DecompressedSizeMismatch`] if the payload decodes to the wrong length.
+    pub fn unpickle(src: &[u8]) -> Result<Vec<u8>> {
+        if src.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (data_off, diff) = header(src)?;
+        let payload = &src[data_off..];
+        if diff == 0 {
+            Ok(payload.to_vec())
+        } else {
+            let expected = payload.len() + diff;
+            let out = block::decompress(payload, expected).map_err(size_error)?;
+            if out.len() != expected {
+                return Err(DecompressedSizeMismatch { expected, got: out.len() }.into());
+            }
+            Ok(out)
+        }
+    }
+
+    /// Like [`unpickle`], but decodes into `out[..len]`, growing `out` only
+    /// if it is shorter, and returns `len`. Reusing one buffer across
+    /// blocks avoids an allocation and a copy per block.
+    pub fn unpickle_into(src: &[u8], out: &mut Vec<u8>) -> Result<usize> {
+        if src.is_empty() {
+            return Ok(0);
+        }
+
+        let (data_off, diff) = header(src)?;
+        let payload = &src[data_off..];
+        let len = payload.len() + diff;
+        if out.len() < len {
+            out.resize(len, 0);
+        }
+        if diff == 0 {
+            out[..len].copy_from_slice(payload);
+        } else {
+            let n = block::decompress_into(payload, &mut out[..len]).map_err(size_error)?;
+            if n != len {
+                return Err(DecompressedSizeMismatch { expected: len, got: n }.into());
+            }
+        }
+        Ok(len)
+    }
+
+    /// Upper bound on the pickled size of `len` source bytes from any
+    /// writer: the longest header plus LZ4's worst‑case expansion.
+    pub fn max_pickled_len(len: usize) -> usize {
+        len.saturating_add(len / 255).saturating_add(5 + 16)
+    }
+
+    /// Decoded size of a pickled block, read from its header alone.
+    pub fn unpickled_len(src: &[u8]) -> Result<usize> {
+        if src.is_empty() {
+            return Ok(0);
+        }
+        let (data_off, diff) = header(src)?;
+        Ok(src.len() - data_off + diff)
+    }
+
+    /// Returns `(payload offset, diff)`.
+    pub(crate) fn header(src: &[u8]) -> Result<(usize, usize)> {
+        let b0 = src[0];
+        if b0 & 7 != 0 { return Err(UnsupportedVersion(b0 & 7).into()); }
+
+        let diff_len = match (b0 >> 6) & 3 { 0 => 0, 1 => 1, 2 => 2, _ => 4 };
+        let data_off = 1 + diff_len;
+        if src.len() < data_off { return Err(FastStorageError::CorruptBlock("pickle header truncated".into())); }
+
+        let diff = if diff_len == 0 {
+            0
+        } else {
+            let mut tmp = [0u8; 4];
+            tmp[..diff_len].copy_from_slice(&src[1..data_off]);
+            LittleEndian::read_u32(&tmp) as usize
+        };
+        Ok((data_off, diff))
+    }
+}
+
+/// CRC‑32 (IEEE 802.3, reflected, as in zlib) for optional block checksums.
+pub mod crc32 {
+    const TABLE: [u32; 256] = {
+        let mut t = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+                k += 1;
+            }
+            t[i] = c;
+            i += 1;
+        }
+        t
+    };
+
+    pub fn checksum(data: &[u8]) -> u32 {
+        !data.iter().fold(!0u32, |c, &b| TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8))
+    }
+}
+
+/* ────────────────  2. wire‑format structs  ─────────────────────────── */
+
+#[repr(i16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKind { Depth = 0, Tick = 1, Symbol = 2, Candle = 3, CandleEnd = 4 }
+
+/// A raw `header.kind` that names no [`MessageKind`], as returned by
+/// `MessageKind::try_from`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownKind(pub i16);
+
+impl core::fmt::Display for UnknownKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "unknown message kind {}", self.0)
+    }
+}
+
+impl core::error::Error for UnknownKind {}
+
+impl TryFrom<i16> for MessageKind {
+    type Error = UnknownKind;
+
+    fn try_from(kind: i16) -> Result<Self, UnknownKind> {
+        Ok(match kind {
+            0 => Self::Depth,
+            1 => Self::Tick,
+            2 => Self::Symbol,
+            3 => Self::Candle,
+            4 => Self::CandleEnd,
+            _ => return Err(UnknownKind(kind)),
+        })
+    }
+}
+
+impl MessageKind {
+    /// Exact encoded size of this kind, header included; `None` for
+    /// symbols, whose length depends on the name.
+    pub const fn wire_size(self) -> Option<usize> {
+        match self {
+            Self::Depth     => Some(size_of::<DepthItem>()),
+            Self::Tick      => Some(size_of::<TickItem>()),
+            Self::Symbol    => None,
+            Self::Candle    => Some(size_of::<CandleItem>()),
+            Self::CandleEnd => Some(size_of::<MessageHeader>()),
+        }
+    }
+}
+
+bitflags! {
+    #[repr(transparent)]
+    #[derive(Default)]
+    pub struct MarketFlag: u8 {
+        const BUY       = 1;
+        const SELL      = 2;
+        const CLEAR     = 4;
+        const END_OF_TX = 8;
+    }
+}
+
+/// Aggressor side of a trade, from [`TickItem::side`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side { Buy, Sell, Unknown }
+
+/// Common prefix of every message, 12 bytes:
+///
+/// | offset | field | type |
+/// |-------:|-------|------|
+/// |      0 | kind  | i16 (`MessageKind`) |
+/// |      2 | size  | u16, whole message incl. header |
+/// |      4 | time  | i64  |
+///
+/// The format itself doesn't fix the unit of `time`; the reader only
+/// compares and subtracts it. Captures from the .NET writer store
+/// `DateTime.Ticks` (100 ns since 0001‑01‑01, UTC), which
+/// [`MessageHeader::to_unix_nanos`] and `MessageHeader::to_system_time`
+/// convert.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct MessageHeader { pub kind: i16, pub size: u16, pub time: i64 }
+
+/// One price‑level update, 29 bytes. There is no sequence number: the
+/// i64 at offset 4 that older code read as `_seq` is `header.time`.
+///
+/// | offset | field  | type |
+/// |-------:|--------|------|
+/// |      0 | header | `MessageHeader` (12) |
+/// |     12 | price  | i64, ×1e8 |
+/// |     20 | volume | i64, ×1e8; 0 removes the level |
+/// |     28 | flags  | u8 (`MarketFlag`) |
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct DepthItem { pub header: MessageHeader, price: i64, volume: i64, flags: u8 }
+
+/// One trade, 37 bytes.
+///
+/// | offset | field  | type |
+/// |-------:|--------|------|
+/// |      0 | header | `MessageHeader` (12) |
+/// |     12 | id     | i64, exchange trade id |
+/// |     20 | price  | i64, ×1e8 |
+/// |     28 | volume | i64, ×1e8 |
+/// |     36 | side   | u8, `MarketFlag::BUY` (1) or `MarketFlag::SELL` (2) |
+///
+/// The writer stores `side` as given; captures set exactly one of the two
+/// bits. [`TickItem::side`] reads `BUY` first, and neither bit as
+/// [`Side::Unknown`].
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct TickItem  { pub header: MessageHeader, id: i64, price: i64, volume: i64, side: u8 }
+
+/// OHLCV bar, 52 bytes. Prices and volume are fixed‑point ×1e8 like the
+/// other kinds:
+///
+/// | offset | field  | type |
+/// |-------:|--------|------|
+/// |      0 | header | `MessageHeader` (12) |
+/// |     12 | open   | i64  |
+/// |     20 | high   | i64  |
+/// |     28 | low    | i64  |
+/// |     36 | close  | i64  |
+/// |     44 | volume | i64  |
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct CandleItem {
+    pub header: MessageHeader,
+    open:   i64,
+    high:   i64,
+    low:    i64,
+    close:  i64,
+    volume: i64,
+}
+
+/// Fixed‑point divisors turning raw prices and volumes into `f64`. The
+/// default, 1e8 for both, matches the feeds this crate was written for;
+/// venues quoting in 1e6 or 1e9 need their own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scale { pub price_divisor: f64, pub volume_divisor: f64 }
+
+impl Scale {
+    pub const DEFAULT: Scale = Scale { price_divisor: 1e8, volume_divisor: 1e8 };
+
+    pub fn price(&self, raw: i64) -> f64 { raw as f64 / self.price_divisor }
+    pub fn volume(&self, raw: i64) -> f64 { raw as f64 / self.volume_divisor }
+}
+
+impl Default for Scale {
+    fn default() -> Self { Self::DEFAULT }
+}
+
+// The payload fields sit at odd offsets in packed structs, so they are
+// private and read through by‑value getters: a reference to one would be
+// misaligned. `header` stays public, as `MessageHeader` is itself packed
+// to alignment 1.
+
+impl MessageHeader {
+    fn of(kind: MessageKind, time: i64) -> Self {
+        MessageHeader { kind: kind as i16, size: kind.wire_size().unwrap_or(0) as u16, time }
+    }
+
+    /// `time` read as .NET ticks, as nanoseconds since the Unix epoch.
+    /// `None` outside what an `i64` of nanoseconds holds (years 1677 to 2262).
+    pub fn to_unix_nanos(&self) -> Option<i64> {
+        ({ self.time }).checked_sub(DOTNET_UNIX_EPOCH_TICKS)?.checked_mul(100)
+    }
+
+    /// `time` read as .NET ticks, as a `SystemTime`. `None` if the
+    /// platform's `SystemTime` can't represent it. Needs the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn to_system_time(&self) -> Option<std::time::SystemTime> {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let ticks = ({ self.time }).checked_sub(DOTNET_UNIX_EPOCH_TICKS)?;
+        let since = Duration::new(ticks.unsigned_abs() / 10_000_000, (ticks.unsigned_abs() % 10_000_000) as u32 * 100);
+        if ticks >= 0 { UNIX_EPOCH.checked_add(since) } else { UNIX_EPOCH.checked_sub(since) }
+    }
+}
+
+/// .NET `DateTime.Ticks` of 1970‑01‑01T00:00:00Z.
+pub const DOTNET_UNIX_EPOCH_TICKS: i64 = 621_355_968_000_000_000;
+
+impl DepthItem {
+    pub fn new(time: i64, price: i64, volume: i64, flags: u8) -> Self {
+        DepthItem { header: MessageHeader::of(MessageKind::Depth, time), price, volume, flags }
+    }
+
+    #[inline] pub fn price(&self) -> i64 { self.price }
+    #[inline] pub fn volume(&self) -> i64 { self.volume }
+    #[inline] pub fn flags(&self) -> u8 { self.flags }
+
+    /// `flags` with unknown bits dropped.
+    pub fn market_flags(&self) -> MarketFlag { MarketFlag::from_bits_truncate(self.flags) }
+
+    pub fn scaled_price(&self, scale: &Scale) -> f64 { scale.price(self.price) }
+    pub fn scaled_volume(&self, scale: &Scale) -> f64 { scale.volume(self.volume) }
+}
+
+impl TickItem {
+    pub fn new(time: i64, id: i64, price: i64, volume: i64, side: u8) -> Self {
+        TickItem { header: MessageHeader::of(MessageKind::Tick, time), id, price, volume, side }
+    }
+
+    #[inline] pub fn id(&self) -> i64 { self.id }
+    #[inline] pub fn price(&self) -> i64 { self.price }
+    #[inline] pub fn volume(&self) -> i64 { self.volume }
+    /// `side` as stored; see [`TickItem::side`] for the decoded value.
+    #[inline] pub fn raw_side(&self) -> u8 { self.side }
+
+    pub fn scaled_price(&self, scale: &Scale) -> f64 { scale.price(self.price) }
+    pub fn scaled_volume(&self, scale: &Scale) -> f64 { scale.volume(self.volume) }
+
+    pub fn side(&self) -> Side {
+        let side = self.side;   // packed: copy out before matching
+        match MarketFlag::from_bits_truncate(side) {
+            s if s.contains(MarketFlag::BUY)  => Side::Buy,
+            s if s.contains(MarketFlag::SELL) => Side::Sell,
+            _                                 => Side::Unknown,
+        }
+    }
+}
+
+impl CandleItem {
+    pub fn new(time: i64, open: i64, high: i64, low: i64, close: i64, volume: i64) -> Self {
+        CandleItem { header: MessageHeader::of(MessageKind::Candle, time), open, high, low, close, volume }
+    }
+
+    #[inline] pub fn open(&self) -> i64 { self.open }
+    #[inline] pub fn high(&self) -> i64 { self.high }
+    #[inline] pub fn low(&self) -> i64 { self.low }
+    #[inline] pub fn close(&self) -> i64 { self.close }
+    #[inline] pub fn volume(&self) -> i64 { self.volume }
+}
+
+// The structs are cast straight onto block bytes; pin them to the wire sizes.
+const _: () = assert!(size_of::<MessageHeader>() == 12);
+const _: () = assert!(size_of::<DepthItem>() == 29);
+const _: () = assert!(size_of::<TickItem>() == 37);
+const _: () = assert!(size_of::<CandleItem>() == 52);
+
+/// Instrument marker. The name occupies the rest of the message after the
+/// header (`header.size - 12` bytes); trailing NUL padding is dropped.
+/// Messages that follow belong to this symbol until the next marker.
+#[derive(Clone, Debug)]
+pub struct SymbolItem { pub header: MessageHeader, pub name: String }
+
+/// A decoded message, copied out of the block buffer.
+#[derive(Clone, Debug)]
+pub enum Message {
+    Depth(DepthItem),
+    Tick(TickItem),
+    Symbol(SymbolItem),
+    Candle(CandleItem),
+    CandleEnd(MessageHeader),
+}
+
+impl Message {
+    /// Appends the wire encoding to `out`. For symbols the header's `size`
+    /// is recomputed from the name length.
+    pub fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Message::Depth(d)     => out.extend_from_slice(bytes_of(d)),
+            Message::Tick(t)      => out.extend_from_slice(bytes_of(t)),
+            Message::Candle(c)    => out.extend_from_slice(bytes_of(c)),
+            Message::CandleEnd(h) => out.extend_from_slice(bytes_of(h)),
+            Message::Symbol(s)    => {
+                let size = (size_of::<MessageHeader>() + s.name.len()) as u16;
+                out.extend_from_slice(bytes_of(&MessageHeader { size, ..s.header }));
+                out.extend_from_slice(s.name.as_bytes());
+            }
+        }
+    }
+}
+
+/// Raw bytes of a packed wire struct (no padding, so every byte is initialised).
+fn bytes_of<T: Copy>(v: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(v as *const T as *const u8, size_of::<T>()) }
+}
+
+/// How symbol names that are not valid UTF‑8 are surfaced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymbolDecoding {
+    /// Reject the name with an error.
+    #[default]
+    Strict,
+    /// Replace invalid sequences with U+FFFD.
+    Lossy,
+}
+
+impl SymbolDecoding {
+    pub fn decode(self, raw: &[u8]) -> Result<String> {
+        match (core::str::from_utf8(raw), self) {
+            (Ok(s), _)                       => Ok(s.to_owned()),
+            (Err(_), SymbolDecoding::Lossy)  => Ok(String::from_utf8_lossy(raw).into_owned()),
+            (Err(e), SymbolDecoding::Strict) => {
+                let msg = format!("symbol name is not valid UTF-8 (invalid byte at {})", e.valid_up_to());
+                Err(FastStorageError::CorruptMessage(msg))
+            }
+        }
+    }
+}
+
+/* ────────────────  3. errors  ──────────────────────────────────────── */
+
+/// The file ends part‑way through a block's length prefix or payload, as
+/// a capture that crashed mid‑write leaves it. Ending exactly on a block
+/// boundary is a clean end of stream instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TruncatedFile {
+    /// File offset of the incomplete block's length prefix.
+    pub block_pos: u64,
+    /// Bytes still expected when the file ended.
+    pub missing: usize,
+}
+
+impl core::fmt::Display for TruncatedFile {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "file truncated in block at byte {}: {} bytes missing", self.block_pos, self.missing)
+    }
+}
+
+impl core::error::Error for TruncatedFile {}
+
+/// A message whose header `size` runs past the end of its decompressed
+/// block, typically the last one of a capture stopped mid‑write. Fewer
+/// trailing bytes than a header are not an error: they just end the block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartialMessage {
+    /// Offset of the message inside the block.
+    pub offset:    usize,
+    /// `header.size`.
+    pub size:      usize,
+    /// Bytes left in the block from `offset`.
+    pub available: usize,
+}
+
+impl core::fmt::Display for PartialMessage {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "message at offset {} overruns block ({} bytes, {} left)", self.offset, self.size, self.available)
+    }
+}
+
+impl core::error::Error for PartialMessage {}
+
+/// A message whose `header.time` is below the one handed out before it,
+/// as `FastCacheReader::set_strict_time` reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfOrderTime {
+    /// Index of the block holding the later message, counting from 0.
+    pub block:    u64,
+    /// Time of the message before it.
+    pub previous: i64,
+    pub time:     i64,
+}
+
+impl core::fmt::Display for OutOfOrderTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "time went backwards in block {}: {} after {}", self.block, self.time, self.previous)
+    }
+}
+
+impl core::error::Error for OutOfOrderTime {}
+
+/// Every failure the library reports. Wrapping a value in
+/// [`FastStorageError::Context`] records where it happened (a path, a
+/// block offset) without hiding the cause; match on [`root`](Self::root)
+/// to branch on the kind. Display prints the context chain, outermost first.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum FastStorageError {
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// The file has no bytes at all, not even a header. A file holding
+    /// just the header is a valid empty stream.
+    EmptyFile,
+    /// The file is shorter than its 4‑byte header.
+    HeaderTruncated,
+    Truncated(TruncatedFile),
+    UnsupportedVersion(k4os_pickler::UnsupportedVersion),
+    /// A block that would decompress to more than the reader's limit (see
+    /// `FastCacheReader::set_max_block_len`), or a file header declaring
+    /// a buffer above it.
+    BlockTooLarge { len: usize, limit: usize },
+    /// A block whose stored CRC‑32 doesn't match its decompressed bytes.
+    ChecksumMismatch { block: u64, stored: u32, computed: u32 },
+    DecompressFailed(lz4_flex::block::DecompressError),
+    SizeMismatch(k4os_pickler::DecompressedSizeMismatch),
+    /// A file header or block frame that can't be right: a zero buffer
+    /// length, a non‑positive block length, or fewer bytes than a pickle
+    /// header.
+    CorruptBlock(String),
+    PartialMessage(PartialMessage),
+    OutOfOrder(OutOfOrderTime),
+    UnknownKind(UnknownKind),
+    /// A message that can't be what its header says: a `size` below a
+    /// header's, a length that doesn't fit its kind, or a symbol name that
+    /// isn't UTF‑8.
+    CorruptMessage(String),
+    /// A bad argument, or an index or file set that doesn't fit together.
+    Invalid(String),
+    Context { context: String, source: Box<FastStorageError> },
+}
+
+/// `Result` with [`FastStorageError`] as the default error.
+pub type Result<T, E = FastStorageError> = core::result::Result<T, E>;
+
+impl FastStorageError {
+    /// The error beneath any [`Context`](Self::Context) layers.
+    pub fn root(&self) -> &FastStorageError {
+        let mut e = self;
+        while let FastStorageError::Context { source, .. } = e { e = source; }
+        e
+    }
+
+    #[doc(hidden)]
+    pub fn context(self, context: impl core::fmt::Display) -> Self {
+        FastStorageError::Context { context: context.to_string(), source: Box::new(self) }
+    }
+}
+
+impl core::fmt::Display for FastStorageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use FastStorageError::*;
+        match self {
+            #[cfg(feature = "std")]
+            Io(e)                                 => e.fmt(f),
+            EmptyFile                             => f.write_str("file is empty (no header)"),
+            HeaderTruncated                       => f.write_str("file header truncated"),
+            Truncated(e)                          => e.fmt(f),
+            UnsupportedVersion(e)                 => e.fmt(f),
+            BlockTooLarge { len, limit }          => write!(f, "block of {len} bytes exceeds the {limit} byte limit"),
+            ChecksumMismatch { block, stored, computed } => {
+                write!(f, "checksum mismatch in block {block}: stored {stored:08x}, computed {computed:08x}")
+            }
+            DecompressFailed(e)                   => e.fmt(f),
+            SizeMismatch(e)                       => e.fmt(f),
+            CorruptBlock(msg) | CorruptMessage(msg) | Invalid(msg) => f.write_str(msg),
+            PartialMessage(e)                     => e.fmt(f),
+            OutOfOrder(e)                         => e.fmt(f),
+            UnknownKind(e)                        => e.fmt(f),
+            Context { context, source }           => write!(f, "{context}: {source}"),
+        }
+    }
+}
+
+impl core::error::Error for FastStorageError {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for FastStorageError {
+    fn from(e: std::io::Error) -> Self { FastStorageError::Io(e) }
+}
+
+impl From<TruncatedFile> for FastStorageError {
+    fn from(e: TruncatedFile) -> Self { FastStorageError::Truncated(e) }
+}
+
+impl From<k4os_pickler::UnsupportedVersion> for FastStorageError {
+    fn from(e: k4os_pickler::UnsupportedVersion) -> Self { FastStorageError::UnsupportedVersion(e) }
+}
+
+impl From<lz4_flex::block::DecompressError> for FastStorageError {
+    fn from(e: lz4_flex::block::DecompressError) -> Self { FastStorageError::DecompressFailed(e) }
+}
+
+impl From<k4os_pickler::DecompressedSizeMismatch> for FastStorageError {
+    fn from(e: k4os_pickler::DecompressedSizeMismatch) -> Self { FastStorageError::SizeMismatch(e) }
+}
+
+impl From<PartialMessage> for FastStorageError {
+    fn from(e: PartialMessage) -> Self { FastStorageError::PartialMessage(e) }
+}
+
+impl From<OutOfOrderTime> for FastStorageError {
+    fn from(e: OutOfOrderTime) -> Self { FastStorageError::OutOfOrder(e) }
+}
+
+impl From<UnknownKind> for FastStorageError {
+    fn from(e: UnknownKind) -> Self { FastStorageError::UnknownKind(e) }
+}
+
+/// `anyhow`‑style `.with_context()` for the library's own results.
+#[doc(hidden)]
+pub trait Context<T> {
+    fn with_context<C: core::fmt::Display>(self, f: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Into<FastStorageError>> Context<T> for core::result::Result<T, E> {
+    fn with_context<C: core::fmt::Display>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+/// Status codes returned by the C ABI of `faststorage_native`. Success is 0 (or, for
+/// `read_message`/`read_message_batch`, a size or count, with 0 meaning
+/// end of stream); failures are these stable negative values. The codes
+/// never change meaning; new ones only get added below the last.
+pub const ERR_INVALID_ARGS:        i32 = -1;
+pub const ERR_DECOMPRESS:          i32 = -2;
+pub const ERR_CORRUPT_HEADER:      i32 = -3;
+pub const ERR_TRUNCATED:           i32 = -4;
+pub const ERR_CORRUPT_MESSAGE:     i32 = -5;
+pub const ERR_CHECKSUM:            i32 = -6;
+pub const ERR_UNSUPPORTED_VERSION: i32 = -7;
+pub const ERR_BLOCK_TOO_LARGE:     i32 = -8;
+pub const ERR_IO:                  i32 = -9;
+pub const ERR_EMPTY_FILE:          i32 = -10;
+
+impl FastStorageError {
+    /// The `ERR_*` code the C ABI reports for this error.
+    pub fn code(&self) -> i32 {
+        use FastStorageError::*;
+        match self.root() {
+            #[cfg(feature = "std")]
+            Io(_)                                  => ERR_IO,
+            EmptyFile                              => ERR_EMPTY_FILE,
+            HeaderTruncated | Truncated(_)         => ERR_TRUNCATED,
+            UnsupportedVersion(_)                  => ERR_UNSUPPORTED_VERSION,
+            BlockTooLarge { .. }                   => ERR_BLOCK_TOO_LARGE,
+            ChecksumMismatch { .. }                => ERR_CHECKSUM,
+            DecompressFailed(_) | SizeMismatch(_)  => ERR_DECOMPRESS,
+            CorruptBlock(_)                        => ERR_CORRUPT_HEADER,
+            PartialMessage(_) | OutOfOrder(_) | UnknownKind(_) | CorruptMessage(_) => ERR_CORRUPT_MESSAGE,
+            Invalid(_) | Context { .. }            => ERR_INVALID_ARGS,
+        }
+    }
+}
+
+/* ────────────────  4. block framing  ───────────────────────────────── */
+
+// The hidden items below are the framing steps `faststorage_native`'s
+// readers share with this crate; they are public for that crate's sake,
+// not part of the API.
+
+/// Bit 31 of the file‑header word: every block is followed by a CRC‑32 of
+/// its decompressed bytes. Readers without checksum support see a negative
+/// buffer length and reject the file rather than misparse it.
+pub const CHECKSUM_FLAG: i32 = i32::MIN;
+
+/// First four bytes of a zstd frame, read as a little‑endian header word.
+/// Such a file is an archived capture still wrapped in zstd; there is no
+/// zstd decoder in the crate, so it is rejected with a hint instead of as
+/// a huge buffer length.
+pub const ZSTD_MAGIC: i32 = 0xFD2F_B528_u32 as i32;
+
+/// Byte order of a file's integers: the header word, the block length
+/// prefixes and checksums, and the message fields. Files from the x86
+/// writer, and everything `FastCacheWriter` produces, are little‑endian.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    #[doc(hidden)]
+    pub fn read_i32(self, b: &[u8]) -> i32 {
+        match self { Endian::Little => LittleEndian::read_i32(b), Endian::Big => BigEndian::read_i32(b) }
+    }
+
+    #[doc(hidden)]
+    pub fn read_u32(self, b: &[u8]) -> u32 {
+        match self { Endian::Little => LittleEndian::read_u32(b), Endian::Big => BigEndian::read_u32(b) }
+    }
+}
+
+/// Rewrites the messages of a decompressed big‑endian block in place into
+/// the little‑endian layout the wire structs expect. Stops at the first
+/// header that doesn't describe a whole message and leaves the rest for
+/// `message_range` to report; payloads of unknown kinds are left as is.
+#[doc(hidden)]
+pub fn swap_block(block: &mut [u8]) {
+    let mut at = 0;
+    while block.len() - at >= size_of::<MessageHeader>() {
+        let m = &mut block[at..];
+        m[0..2].reverse();
+        m[2..4].reverse();
+        m[4..12].reverse();
+        let size = LittleEndian::read_u16(&m[2..4]) as usize;
+        if size < size_of::<MessageHeader>() || size > m.len() { return; }
+        let fields: &[usize] = match MessageKind::try_from(LittleEndian::read_i16(m)) {
+            Ok(MessageKind::Depth)  => &[12, 20],
+            Ok(MessageKind::Tick)   => &[12, 20, 28],
+            Ok(MessageKind::Candle) => &[12, 20, 28, 36, 44],
+            _                       => &[],
+        };
+        for &f in fields.iter().filter(|&&f| f + 8 <= size) { m[f..f + 8].reverse(); }
+        at += size;
+    }
+}
+
+/// Largest decompressed block the reader accepts unless told otherwise.
+pub const DEFAULT_MAX_BLOCK_LEN: usize = 64 << 20;
+
+/// Byte range of the message at `at`, or `None` for a `size == 0`
+/// terminator. The caller guarantees a full header fits at `at`.
+#[doc(hidden)]
+pub fn message_range(block: &[u8], at: usize) -> Result<Option<Range<usize>>> {
+    debug_assert!(at + size_of::<MessageHeader>() <= block.len());
+    let h = unsafe { ptr::read_unaligned(block.as_ptr().add(at) as *const MessageHeader) };
+    if h.size == 0 { return Ok(None); }
+    if (h.size as usize) < size_of::<MessageHeader>() {
+        return Err(FastStorageError::CorruptMessage(format!("message at offset {at} has invalid size {}", { h.size })));
+    }
+    let end = at + h.size as usize;
+    if end > block.len() {
+        return Err(PartialMessage { offset: at, size: h.size as usize, available: block.len() - at }.into());
+    }
+    Ok(Some(at..end))
+}
+
+/// Whether walking `block` reaches a `size == 0` terminator before its end
+/// or a malformed header.
+#[doc(hidden)]
+pub fn has_terminator(block: &[u8]) -> bool {
+    let mut at = 0;
+    while block.len() - at >= size_of::<MessageHeader>() {
+        match message_range(block, at) {
+            Ok(Some(r)) => at = r.end,
+            Ok(None)    => return true,
+            Err(_)      => return false,
+        }
+    }
+    false
+}
+
+/// Decompresses `cmp_buf` into `src`, growing it if needed, and returns
+/// the block length.
+#[doc(hidden)]
+pub fn decode_block(cmp_buf: &[u8], src: &mut Vec<u8>, max_block: usize) -> Result<usize> {
+    check_block_len(cmp_buf, max_block)?;
+    k4os_pickler::unpickle_into(cmp_buf, src)
+}
+
+#[doc(hidden)]
+pub fn unpickle_block(cmp_buf: &[u8], max_block: usize) -> Result<Vec<u8>> {
+    check_block_len(cmp_buf, max_block)?;
+    k4os_pickler::unpickle(cmp_buf)
+}
+
+fn check_block_len(cmp_buf: &[u8], max_block: usize) -> Result<()> {
+    let len = k4os_pickler::unpickled_len(cmp_buf)?;
+    if len > max_block { return Err(FastStorageError::BlockTooLarge { len, limit: max_block }); }
+    Ok(())
+}
+
+#[doc(hidden)]
+pub fn verify_block(index: u64, crc: &[u8], block: &[u8], endian: Endian) -> Result<()> {
+    let (stored, computed) = (endian.read_u32(crc), crc32::checksum(block));
+    if stored != computed { return Err(FastStorageError::ChecksumMismatch { block: index, stored, computed }); }
+    Ok(())
+}
+
+/// Borrowed view of one encoded message (header included).
+#[derive(Clone, Copy)]
+pub struct MessageRef<'a> { bytes: &'a [u8] }
+
+impl<'a> MessageRef<'a> {
+    /// # Safety
+    ///
+    /// `bytes` must hold at least a header, as a range from
+    /// [`message_range`] does.
+    #[doc(hidden)]
+    pub unsafe fn from_framed(bytes: &'a [u8]) -> Self { MessageRef { bytes } }
+
+    pub fn header(&self) -> MessageHeader { self.read() }
+    pub fn kind(&self) -> i16 { self.header().kind }
+    pub fn size(&self) -> u16 { self.header().size }
+    pub fn time(&self) -> i64 { self.header().time }
+
+    /// `None` for kinds this crate doesn't know about.
+    pub fn message_kind(&self) -> Option<MessageKind> { MessageKind::try_from(self.kind()).ok() }
+
+    pub fn bytes(&self) -> &'a [u8] { self.bytes }
+
+    /// The `header.size - 12` bytes after the header, whatever the kind:
+    /// the way to read variable‑length kinds such as symbols, or kinds this
+    /// crate doesn't know. The reader has already checked that `size`
+    /// covers a header and fits in its block.
+    pub fn payload(&self) -> &'a [u8] { &self.bytes[size_of::<MessageHeader>()..] }
+
+    pub fn as_depth(&self) -> Option<DepthItem> { self.read_as(MessageKind::Depth) }
+    pub fn as_tick(&self) -> Option<TickItem> { self.read_as(MessageKind::Tick) }
+    pub fn as_candle(&self) -> Option<CandleItem> { self.read_as(MessageKind::Candle) }
+
+    /// Decodes with strict symbol‑name handling; see [`MessageRef::decode_with`].
+    pub fn decode(&self) -> Result<Message> { self.decode_with(SymbolDecoding::Strict) }
+
+    pub fn decode_with(&self, symbols: SymbolDecoding) -> Result<Message> {
+        match MessageKind::try_from(self.kind())? {
+            MessageKind::Depth     => self.checked().map(Message::Depth),
+            MessageKind::Tick      => self.checked().map(Message::Tick),
+            MessageKind::Symbol    => self.symbol(symbols).map(Message::Symbol),
+            MessageKind::Candle    => self.checked().map(Message::Candle),
+            MessageKind::CandleEnd => self.checked().map(Message::CandleEnd),
+        }
+    }
+
+    /// Errors if the message is not exactly its kind's [`MessageKind::wire_size`];
+    /// symbols and unknown kinds always pass.
+    pub fn check_size(&self) -> Result<()> {
+        let Some(kind) = self.message_kind() else { return Ok(()) };
+        let Some(want) = kind.wire_size() else { return Ok(()) };
+        if self.bytes.len() != want {
+            let msg = format!("{kind:?} message at time {} is {} bytes, expected {want}", self.time(), self.bytes.len());
+            return Err(FastStorageError::CorruptMessage(msg));
+        }
+        Ok(())
+    }
+
+    fn symbol(&self, symbols: SymbolDecoding) -> Result<SymbolItem> {
+        let raw = self.payload();
+        let end = raw.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        let name = symbols.decode(&raw[..end]).with_context(|| format!("symbol at time {}", self.time()))?;
+        Ok(SymbolItem { header: self.header(), name })
+    }
+
+    fn checked<T: Copy>(&self) -> Result<T> {
+        if self.bytes.len() < size_of::<T>() {
+            let msg = format!("kind {} message is {} bytes, expected at least {}", self.kind(), self.bytes.len(), size_of::<T>());
+            return Err(FastStorageError::CorruptMessage(msg));
+        }
+        Ok(self.read())
+    }
+
+    fn read_as<T: Copy>(&self, kind: MessageKind) -> Option<T> {
+        (self.message_kind() == Some(kind) && self.bytes.len() >= size_of::<T>()).then(|| self.read())
+    }
+
+    /// Callers guarantee `bytes` holds at least `size_of::<T>()` bytes; the
+    /// header always fits since the reader checks it before handing out a view.
+    fn read<T: Copy>(&self) -> T {
+        debug_assert!(self.bytes.len() >= size_of::<T>());
+        unsafe { ptr::read_unaligned(self.bytes.as_ptr() as *const T) }
+    }
+}
+
+/// A decompressed block, as from `faststorage_native::ParBlocks`, tagged
+/// with the file offset of its length prefix.
+pub struct DecodedBlock { pub file_pos: u64, data: Vec<u8>, last: bool }
+
+impl DecodedBlock {
+    #[doc(hidden)]
+    pub fn new(file_pos: u64, data: Vec<u8>) -> Self {
+        DecodedBlock { file_pos, last: has_terminator(&data), data }
+    }
+
+    pub fn bytes(&self) -> &[u8] { &self.data }
+
+    /// Whether the block holds a `size == 0` terminator, so that nothing
+    /// after it belongs to the stream.
+    pub fn is_last(&self) -> bool { self.last }
+
+    /// Messages in the block, stopping at a `size == 0` terminator or the
+    /// first malformed header.
+    pub fn messages(&self) -> BlockMessages<'_> { BlockMessages::new(&self.data) }
+}
+
+pub struct BlockMessages<'a> { block: &'a [u8], at: usize }
+
+impl<'a> BlockMessages<'a> {
+    /// Messages of any decompressed block, as [`DecodedBlock::messages`].
+    pub fn new(block: &'a [u8]) -> Self { BlockMessages { block, at: 0 } }
+}
+
+impl<'a> Iterator for BlockMessages<'a> {
+    type Item = Result<MessageRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.block.len() - self.at < size_of::<MessageHeader>() { return None; }
+        match message_range(self.block, self.at) {
+            Ok(Some(r)) => { self.at = r.end; Some(Ok(MessageRef { bytes: &self.block[r] })) }
+            Ok(None)    => { self.at = self.block.len(); None }
+            Err(e)      => { self.at = self.block.len(); Some(Err(e)) }
+        }
+    }
+}
+
+/// Largest back‑reference an LZ4 block can make.
+const LZ4_WINDOW: usize = 0xffff;
+/// Decoded bytes [`ChunkedBlock`] adds per refill.
+const CHUNK_LEN: usize = 16 << 10;
+
+/// Where the LZ4 decoder of a [`ChunkedBlock`] stopped.
+#[derive(Clone, Copy)]
+enum Lz4Seq {
+    Token,
+    Literals { left: usize, match_code: usize },
+    Match { off: usize, left: usize },
+}
+
+/// Messages of one pickled block, decompressed a chunk at a time instead
+/// of all at once, for blocks too large to want a buffer of their full
+/// decoded size. Only LZ4's 64 KiB back‑reference window, the message
+/// being read and one 16 KiB chunk are held, so memory stays under
+/// 160 KiB whatever the block size.
+///
+/// Takes the pickled bytes alone, without the frame's length prefix and
+/// checksum, and reads messages as little‑endian. As in
+/// [`BlockMessages`], a `size == 0` terminator or a tail shorter than a
+/// header ends the block, and the first error ends it too. The decoded
+/// length is checked against the header once the payload runs out.
+/// Decoding stops at a terminator, so bytes after one are not checked.
+pub struct ChunkedBlock<'a> {
+    payload:  &'a [u8],
+    ip:       usize,       // next payload byte
+    stored:   bool,        // diff 0: the payload is the block itself
+    expected: usize,       // decoded length, from the header
+    seq:      Lz4Seq,
+    buf:      Vec<u8>,     // decoded bytes from `base` on
+    base:     usize,
+    at:       usize,       // next message, as an offset into the block
+    ended:    bool,
+}
+
+impl<'a> ChunkedBlock<'a> {
+    pub fn new(pickled: &'a [u8]) -> Result<Self> {
+        let (data_off, diff) = if pickled.is_empty() { (0, 0) } else { k4os_pickler::header(pickled)? };
+        let payload = &pickled[data_off..];
+        Ok(ChunkedBlock {
+            payload, ip: 0, stored: diff == 0, expected: payload.len() + diff, seq: Lz4Seq::Token,
+            buf: Vec::with_capacity(LZ4_WINDOW + u16::MAX as usize + CHUNK_LEN), base: 0, at: 0, ended: false,
+        })
+    }
+
+    pub fn next_message(&mut self) -> Result<Option<MessageRef<'_>>> {
+        if self.ended { return Ok(None); }
+        match self.next_range() {
+            Ok(Some(r)) => Ok(Some(MessageRef { bytes: &self.buf[r] })),
+            r => { self.ended = true; r.map(|_| None) }
+        }
+    }
+
+    /// Range in `buf` of the next message, decoding as far as it reaches.
+    fn next_range(&mut self) -> Result<Option<Range<usize>>> {
+        const HDR: usize = size_of::<MessageHeader>();
+        while self.decoded() - self.at < HDR {
+            if !self.fill()? { return Ok(None); }
+        }
+        let rel = self.at - self.base;
+        let size = LittleEndian::read_u16(&self.buf[rel + 2..rel + 4]) as usize;
+        if size == 0 { return Ok(None); }
+        if size < HDR {
+            return Err(FastStorageError::CorruptMessage(format!("message at offset {} has invalid size {size}", self.at)));
+        }
+        while self.decoded() - self.at < size {
+            if !self.fill()? {
+                return Err(PartialMessage { offset: self.at, size, available: self.decoded() - self.at }.into());
+            }
+        }
+        let rel = self.at - self.base;
+        self.at += size;
+        Ok(Some(rel..rel + size))
+    }
+
+    fn decoded(&self) -> usize { self.base + self.buf.len() }
+
+    /// Decodes up to one more chunk; false once the payload is used up.
+    fn fill(&mut self) -> Result<bool> {
+        // Drop what neither the next message nor a back‑reference still needs.
+        let keep = self.at.min(self.decoded().saturating_sub(LZ4_WINDOW));
+        if keep > self.base {
+            self.buf.drain(..keep - self.base);
+            self.base = keep;
+        }
+        let (start, goal) = (self.buf.len(), self.buf.len() + CHUNK_LEN);
+        if self.stored {
+            let n = CHUNK_LEN.min(self.payload.len() - self.ip);
+            self.buf.extend_from_slice(&self.payload[self.ip..self.ip + n]);
+            self.ip += n;
+        } else {
+            while self.buf.len() < goal && self.step(goal)? {}
+        }
+        let got = self.decoded();
+        if got > self.expected || (self.buf.len() == start && got != self.expected) {
+            return Err(k4os_pickler::DecompressedSizeMismatch { expected: self.expected, got }.into());
+        }
+        Ok(self.buf.len() > start)
+    }
+
+    /// Advances the LZ4 decoder by one state, without decoding past
+    /// `goal`; false at the end of the payload.
+    fn step(&mut self, goal: usize) -> Result<bool> {
+        match self.seq {
+            Lz4Seq::Token => {
+                let Some(&token) = self.payload.get(self.ip) else { return Ok(false) };
+                self.ip += 1;
+                let left = self.ext_len((token >> 4) as usize)?;
+                self.seq = Lz4Seq::Literals { left, match_code: (token & 15) as usize };
+            }
+            Lz4Seq::Literals { left, match_code } => {
+                let n = left.min(goal - self.buf.len());
+                let lits = self.payload.get(self.ip..self.ip + n).ok_or_else(|| lz4_error("literals run past the end of the block"))?;
+                self.buf.extend_from_slice(lits);
+                self.ip += n;
+                if n < left {
+                    self.seq = Lz4Seq::Literals { left: left - n, match_code };
+                    return Ok(true);
+                }
+                // The last sequence is literals only.
+                if self.ip == self.payload.len() {
+                    self.seq = Lz4Seq::Token;
+                    return Ok(false);
+                }
+                let off = self.payload.get(self.ip..self.ip + 2).ok_or_else(|| lz4_error("match offset truncated"))?;
+                let off = LittleEndian::read_u16(off) as usize;
+                self.ip += 2;
+                if off == 0 || off > self.decoded() {
+                    return Err(lz4_error(&format!("match offset {off} at decoded byte {}", self.decoded())));
+                }
+                self.seq = Lz4Seq::Match { off, left: self.ext_len(match_code)? + 4 };
+            }
+            Lz4Seq::Match { off, left } => {
+                let n = left.min(goal - self.buf.len());
+                let mut copied = 0;
+                while copied < n {
+                    // An overlapping match repeats the last `off` bytes.
+                    let from = self.buf.len() - off;
+                    let take = (n - copied).min(off);
+                    self.buf.extend_from_within(from..from + take);
+                    copied += take;
+                }
+                self.seq = if n < left { Lz4Seq::Match { off, left: left - n } } else { Lz4Seq::Token };
+            }
+        }
+        Ok(true)
+    }
+
+    /// A token nibble plus the 255‑continued length bytes after it.
+    fn ext_len(&mut self, nibble: usize) -> Result<usize> {
+        let mut len = nibble;
+        if nibble == 15 {
+            loop {
+                let &b = self.payload.get(self.ip).ok_or_else(|| lz4_error("length truncated"))?;
+                self.ip += 1;
+                len += b as usize;
+                if b != 255 { break; }
+            }
+        }
+        Ok(len)
+    }
+}
+
+fn lz4_error(what: &str) -> FastStorageError { FastStorageError::CorruptBlock(format!("corrupt LZ4 data: {what}")) }
+
+/* ────────────────  5. slice reader  ────────────────────────────────── */
+
+/// Message reader over a whole file image in memory: the `no_std`
+/// counterpart of `FastCacheReader::from_bytes`. Blocks are decoded one at
+/// a time into a reused buffer, and a `size == 0` terminator ends the
+/// stream for good. Only little‑endian files are read, and there is no
+/// lenient mode: after an error the reader returns `None`.
+pub struct SliceReader<'a> {
+    data:      &'a [u8],
+    pos:       usize,          // offset of the next block's length prefix
+    src:       Vec<u8>,
+    block_len: usize,
+    offset:    usize,          // next message inside the block
+    buf_len:   usize,          // as declared in the file header
+    checksums: bool,
+    verify:    bool,
+    max_block: usize,
+    blocks:    u64,            // blocks decoded so far
+    ended:     bool,           // hit a terminator or an error
+}
+
+impl<'a> SliceReader<'a> {
+    /// Parses the file header; blocks are only read on demand.
+    pub fn from_bytes(data: &'a [u8]) -> Result<Self> {
+        if data.is_empty() { return Err(FastStorageError::EmptyFile); }
+        if data.len() < 4 { return Err(FastStorageError::HeaderTruncated); }
+        let raw = LittleEndian::read_i32(data);
+        if raw == ZSTD_MAGIC {
+            return Err(FastStorageError::CorruptBlock("file is zstd‑compressed; decompress it first".into()));
+        }
+        let buf_len = raw & !CHECKSUM_FLAG;
+        if buf_len == 0 { return Err(FastStorageError::CorruptBlock("invalid buffer length in file".into())); }
+        Ok(Self {
+            data, pos: 4, src: Vec::new(), block_len: 0, offset: 0, buf_len: buf_len as usize,
+            checksums: raw & CHECKSUM_FLAG != 0, verify: false, max_block: DEFAULT_MAX_BLOCK_LEN,
+            blocks: 0, ended: false,
+        })
+    }
+
+    /// Buffer length declared in the file header.
+    pub fn buffer_len(&self) -> usize { self.buf_len }
+
+    /// Whether the file was written with per‑block checksums.
+    pub fn has_checksums(&self) -> bool { self.checksums }
+
+    /// Checks each block's CRC‑32 when the file has them; off by default.
+    pub fn set_verify_checksums(&mut self, verify: bool) { self.verify = verify; }
+
+    /// Largest decompressed block accepted; [`DEFAULT_MAX_BLOCK_LEN`] by
+    /// default. The header's buffer length is held to it as well.
+    pub fn set_max_block_len(&mut self, max: usize) { self.max_block = max; }
+
+    /// Offset in `data` of the next block's length prefix.
+    pub fn position(&self) -> usize { self.pos }
+
+    /// The next message, borrowed until the next call.
+    pub fn next_message(&mut self) -> Result<Option<MessageRef<'_>>> {
+        match self.advance() {
+            Ok(Some(r)) => Ok(Some(MessageRef { bytes: &self.src[r] })),
+            Ok(None)    => Ok(None),
+            Err(e)      => { self.ended = true; Err(e) }
+        }
+    }
+
+    /// Decodes the next block, skipping whatever is left of the current
+    /// one. Like any block‑level read it doesn't look for terminators.
+    pub fn next_block(&mut self) -> Result<Option<&[u8]>> {
+        if self.ended { return Ok(None); }
+        match self.load() {
+            Ok(true)  => Ok(Some(&self.src[..self.block_len])),
+            Ok(false) => Ok(None),
+            Err(e)    => { self.ended = true; Err(e) }
+        }
+    }
+
+    fn advance(&mut self) -> Result<Option<Range<usize>>> {
+        loop {
+            if self.ended { return Ok(None); }
+            if self.block_len - self.offset >= size_of::<MessageHeader>() {
+                let Some(r) = message_range(&self.src[..self.block_len], self.offset)? else {
+                    self.ended = true;
+                    return Ok(None);
+                };
+                self.offset = r.end;
+                return Ok(Some(r));
+            }
+            if !self.load()? { return Ok(None); }
+        }
+    }
+
+    fn load(&mut self) -> Result<bool> {
+        if self.buf_len > self.max_block {
+            return Err(FastStorageError::BlockTooLarge { len: self.buf_len, limit: self.max_block }
+                .context("file header declares a buffer above the limit (see set_max_block_len)"));
+        }
+        let (at, trailer) = (self.pos, if self.checksums { 4 } else { 0 });
+        if at >= self.data.len() { return Ok(false); }
+        let Some(hdr) = self.data.get(at..at + 4) else {
+            return Err(TruncatedFile { block_pos: at as u64, missing: at + 4 - self.data.len() }.into());
+        };
+        let cmp_len = LittleEndian::read_i32(hdr);
+        if cmp_len <= 0 { return Err(FastStorageError::CorruptBlock(format!("invalid compressed length {cmp_len}"))); }
+
+        let end = at + 4 + cmp_len as usize + trailer;
+        if end > self.data.len() {
+            return Err(TruncatedFile { block_pos: at as u64, missing: end - self.data.len() }.into());
+        }
+        let (cmp_buf, crc) = self.data[at + 4..end].split_at(cmp_len as usize);
+        let len = decode_block(cmp_buf, &mut self.src, self.max_block)?;
+        if self.verify && self.checksums { verify_block(self.blocks, crc, &self.src[..len], Endian::Little)?; }
+        self.pos       = end;
+        self.blocks   += 1;
+        self.block_len = len;
+        self.offset    = 0;
+        Ok(true)
+    }
+}
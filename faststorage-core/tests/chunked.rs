@@ -0,0 +1,106 @@
+//! `ChunkedBlock` against whole‑block decoding, with the heap measured. The
+//! counter is process‑wide, so this file holds a single test.
+
+use faststorage_core::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Relaxed) + layout.size();
+        PEAK.fetch_max(live, Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Runs `f`, returning its result and the most heap it held at once.
+fn peak_during<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let base = LIVE.load(Relaxed);
+    PEAK.store(base, Relaxed);
+    let out = f();
+    (out, PEAK.load(Relaxed) - base)
+}
+
+/// Depth and tick messages whose prices repeat, so LZ4 packs them into
+/// long matches and back‑references.
+fn block(n: i64) -> Vec<u8> {
+    let mut out = Vec::new();
+    for i in 0..n {
+        let msg = if i % 7 == 0 {
+            Message::Tick(TickItem::new(i, i, 100 + i % 13, 1, 1))
+        } else {
+            Message::Depth(DepthItem::new(i, 100 + i % 5, i % 3, 0))
+        };
+        msg.encode_into(&mut out);
+    }
+    out
+}
+
+/// Message count and a running hash: collecting the messages themselves
+/// would take memory proportional to the block.
+fn digest<'a>(msgs: impl IntoIterator<Item = &'a [u8]>) -> (usize, u32) {
+    msgs.into_iter().fold((0, 0), |(n, h), m| (n + 1, h.rotate_left(5) ^ crc32::checksum(m)))
+}
+
+fn chunked_digest(pickled: &[u8]) -> Result<(usize, u32)> {
+    let mut blk = ChunkedBlock::new(pickled)?;
+    let (mut n, mut h) = (0, 0u32);
+    while let Some(m) = blk.next_message()? {
+        n += 1;
+        h = h.rotate_left(5) ^ crc32::checksum(m.bytes());
+    }
+    Ok((n, h))
+}
+
+#[test]
+fn large_block_decodes_in_bounded_memory() {
+    let raw = block(300_000);
+    assert!(raw.len() > 8 << 20);
+    let want = digest(BlockMessages::new(&raw).map(|m| m.unwrap().bytes()));
+    let pickled = k4os_pickler::pickle(&raw).unwrap();
+    let stored = k4os_pickler::pickle_with(&raw, CompressionMode::Stored).unwrap();
+    assert!(pickled.len() < raw.len(), "fixture should compress");
+    drop(raw);
+
+    for p in [&pickled, &stored] {
+        let (got, peak) = peak_during(|| chunked_digest(p).unwrap());
+        assert_eq!(got, want);
+        assert_eq!(got.0, 300_000);
+        assert!(peak < 160 << 10, "peak heap {peak} bytes");
+    }
+
+    // One message repeated: a few matches, each far longer than a chunk.
+    let mut same = Vec::new();
+    for _ in 0..100_000 { Message::Depth(DepthItem::new(1, 2, 3, 0)).encode_into(&mut same); }
+    let p = k4os_pickler::pickle(&same).unwrap();
+    assert!(p.len() < same.len() / 100);
+    let (got, peak) = peak_during(|| chunked_digest(&p).unwrap());
+    assert_eq!(got, digest(BlockMessages::new(&same).map(|m| m.unwrap().bytes())));
+    assert!(peak < 160 << 10, "peak heap {peak} bytes");
+
+    // A terminator ends the block early, as in BlockMessages.
+    let mut raw = block(10);
+    raw.extend_from_slice(&[0; 12]);
+    raw.extend(block(5));
+    let p = k4os_pickler::pickle(&raw).unwrap();
+    assert_eq!(chunked_digest(&p).unwrap(), digest(BlockMessages::new(&raw).map(|m| m.unwrap().bytes())));
+    assert_eq!(chunked_digest(&p).unwrap().0, 10);
+
+    // A payload cut short decodes to fewer bytes than its header promises.
+    let short = &pickled[..pickled.len() - 1000];
+    assert!(chunked_digest(short).is_err());
+    assert_eq!(chunked_digest(&[]).unwrap(), (0, 0));
+}
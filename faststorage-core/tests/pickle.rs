@@ -0,0 +1,85 @@
+use faststorage_core::k4os_pickler::{pickle, pickle_with, unpickle, unpickle_into, unpickled_len, CompressionMode, DecompressedSizeMismatch};
+use faststorage_core::FastStorageError;
+
+/// xorshift64: enough randomness for generated inputs, reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// `len` bytes drawn from an alphabet of `alphabet` symbols, in runs of
+    /// up to `run`: small alphabets and long runs compress, the rest don't.
+    fn bytes(&mut self, len: usize, alphabet: u64, run: u64) -> Vec<u8> {
+        let mut out = Vec::with_capacity(len);
+        while out.len() < len {
+            let (b, n) = ((self.next() % alphabet) as u8, 1 + self.next() % run);
+            out.extend((0..n).map(|_| b).take(len - out.len()));
+        }
+        out
+    }
+}
+
+#[test]
+fn unpickle_inverts_pickle() {
+    assert!(pickle(&[]).unwrap().is_empty());
+    assert!(unpickle(&[]).unwrap().is_empty());
+
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    for case in 0..2_000 {
+        let len = match case % 4 { 0 => (rng.next() % 16) as usize, 1 => (rng.next() % 300) as usize, _ => (rng.next() % 70_000) as usize };
+        let x = rng.bytes(len, [2, 16, 256][case % 3], [1, 8, 300][case % 3]);
+        for mode in [CompressionMode::Lz4, CompressionMode::Stored] {
+            let p = pickle_with(&x, mode).unwrap();
+            assert_eq!(unpickle(&p).unwrap(), x, "case {case}, {mode:?}, {len} bytes");
+            assert_eq!(unpickled_len(&p).unwrap(), x.len());
+            if x.is_empty() { continue; }
+            assert_eq!(p[0] & 7, 0, "version bits");
+            // The diff field is the shortest that holds the saving; none
+            // for a stored block.
+            let diff_len = match p[0] >> 6 { 0 => 0, 1 => 1, 2 => 2, _ => 4 };
+            let diff = x.len() - (p.len() - 1 - diff_len);
+            assert_eq!(diff_len, match diff { 0 => 0, 1..=0xff => 1, 0x100..=0xffff => 2, _ => 4 });
+            if mode == CompressionMode::Stored { assert_eq!(diff, 0); }
+            if diff == 0 { assert_eq!(&p[1..], &x[..]); }
+        }
+    }
+}
+
+#[test]
+fn incompressible_input_is_stored() {
+    let mut rng = Rng(1);
+    let x = rng.bytes(4096, 256, 1);
+    let p = pickle(&x).unwrap();
+    assert_eq!(p[0], 0);
+    assert_eq!(p.len(), x.len() + 1);
+}
+
+#[test]
+fn corrupt_diff_is_a_size_mismatch() {
+    let x = Rng(7).bytes(4000, 4, 16);
+    let p = pickle(&x).unwrap();
+    assert_eq!(p[0] >> 6, 2, "fixture should take a two‑byte diff");
+    let diff = u16::from_le_bytes([p[1], p[2]]) as usize;
+
+    // Too big: the payload runs out early and `got` is exact. Too small:
+    // decoding overruns, so `got` is only what fit.
+    for (wrong, got) in [(diff + 5, Some(x.len())), (diff - 5, None)] {
+        let mut bad = p.clone();
+        bad[1..3].copy_from_slice(&(wrong as u16).to_le_bytes());
+        let expected = x.len() + wrong - diff;
+        for err in [unpickle(&bad).unwrap_err(), unpickle_into(&bad, &mut Vec::new()).unwrap_err()] {
+            match err {
+                FastStorageError::SizeMismatch(DecompressedSizeMismatch { expected: e, got: g }) => {
+                    assert_eq!(e, expected);
+                    if let Some(got) = got { assert_eq!(g, got); }
+                }
+                e => panic!("diff {wrong}: {e}"),
+            }
+        }
+    }
+}
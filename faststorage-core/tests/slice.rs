@@ -0,0 +1,47 @@
+//! The `alloc`‑only entry point, on images built with the core pickler.
+
+use faststorage_core::*;
+
+fn image(buf_len: i32, blocks: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = buf_len.to_le_bytes().to_vec();
+    for b in blocks {
+        let p = k4os_pickler::pickle(b).unwrap();
+        out.extend_from_slice(&(p.len() as i32).to_le_bytes());
+        out.extend_from_slice(&p);
+    }
+    out
+}
+
+fn encode(msgs: &[Message]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for m in msgs { m.encode_into(&mut out); }
+    out
+}
+
+#[test]
+fn slice_reader_decodes_a_borrowed_image() {
+    let first = [Message::Depth(DepthItem::new(1, 2, 3, 1)), Message::Tick(TickItem::new(2, 1, 2, 3, 2))];
+    let second = [Message::Candle(CandleItem::new(3, 1, 2, 3, 4, 5)), Message::Depth(DepthItem::new(4, 5, 6, 0))];
+    let img = image(4096, &[encode(&first), encode(&second)]);
+
+    let mut rdr = SliceReader::from_bytes(&img).unwrap();
+    assert_eq!((rdr.buffer_len(), rdr.has_checksums(), rdr.position()), (4096, false, 4));
+    let mut got = Vec::new();
+    while let Some(m) = rdr.next_message().unwrap() { got.extend_from_slice(m.bytes()); }
+    assert_eq!(got, [encode(&first), encode(&second)].concat());
+    assert_eq!(rdr.position(), img.len());
+    assert!(rdr.next_message().unwrap().is_none());
+
+    assert!(matches!(SliceReader::from_bytes(&[]).err(), Some(FastStorageError::EmptyFile)));
+    let mut cut = SliceReader::from_bytes(&img[..img.len() - 3]).unwrap();
+    let mut n = 0;
+    let err = loop {
+        match cut.next_message() {
+            Ok(Some(_)) => n += 1,
+            Ok(None)    => panic!("no error after {n} messages"),
+            Err(e)      => break e,
+        }
+    };
+    assert_eq!(n, 2);
+    assert_eq!(err.code(), ERR_TRUNCATED);
+}
@@ -0,0 +1,233 @@
+"""Python reader for FastCache files, backed by the native library's C ABI.
+
+    from faststorage import Reader, read_all_ticks
+
+    with Reader("file.bin.lz4") as rdr:
+        for msg in rdr:
+            if isinstance(msg, Tick):
+                print(msg.time, msg.price, msg.volume)
+
+Prices and volumes come back as floats, already divided by 1e8. Only the
+standard library is needed; `read_all_ticks` returns a NumPy structured
+array when NumPy is installed. ctypes drops the GIL for every call into
+the library, so block decompression runs without holding it.
+
+The library is looked up via $FASTSTORAGE_NATIVE_PATH (a file or a
+directory), then next to this file, then in ../target/release.
+"""
+
+import ctypes
+import os
+import struct
+import sys
+from collections import namedtuple
+
+__all__ = [
+    "Reader", "read_all_ticks", "FastStorageError",
+    "ERR_INVALID_ARGS", "ERR_DECOMPRESS", "ERR_CORRUPT_HEADER", "ERR_TRUNCATED", "ERR_CORRUPT_MESSAGE",
+    "ERR_CHECKSUM", "ERR_UNSUPPORTED_VERSION", "ERR_BLOCK_TOO_LARGE", "ERR_IO", "ERR_EMPTY_FILE",
+    "Depth", "Tick", "Symbol", "Candle", "CandleEnd",
+    "KIND_DEPTH", "KIND_TICK", "KIND_SYMBOL", "KIND_CANDLE", "KIND_CANDLE_END",
+]
+
+SCALE = 1e8
+
+Depth     = namedtuple("Depth",     "time price volume flags")
+Tick      = namedtuple("Tick",      "time id price volume side")
+Symbol    = namedtuple("Symbol",    "time name")
+Candle    = namedtuple("Candle",    "time open high low close volume")
+CandleEnd = namedtuple("CandleEnd", "time")
+
+_HEADER = struct.Struct("<hHq")
+_DEPTH  = struct.Struct("<12xqqB")
+_TICK   = struct.Struct("<12xqqqB")
+_CANDLE = struct.Struct("<12xqqqqq")
+_TICK_LEN = _TICK.size
+
+KIND_DEPTH, KIND_TICK, KIND_SYMBOL, KIND_CANDLE, KIND_CANDLE_END = range(5)
+
+_BATCH = 4096
+
+
+class FastStorageError(Exception):
+    """`code` is the native library's ERR_* value (a negative int), or None
+    for errors raised on the Python side."""
+
+    def __init__(self, message, code=None):
+        super().__init__(message)
+        self.code = code
+
+
+# Native status codes; see ERR_* in src/lib.rs.
+ERR_INVALID_ARGS        = -1
+ERR_DECOMPRESS          = -2
+ERR_CORRUPT_HEADER      = -3
+ERR_TRUNCATED           = -4
+ERR_CORRUPT_MESSAGE     = -5
+ERR_CHECKSUM            = -6
+ERR_UNSUPPORTED_VERSION = -7
+ERR_BLOCK_TOO_LARGE     = -8
+ERR_IO                  = -9
+ERR_EMPTY_FILE          = -10
+
+
+def _lib_names():
+    if sys.platform == "darwin":
+        return ["libfaststorage_native.dylib"]
+    if sys.platform == "win32":
+        return ["faststorage_native.dll"]
+    return ["libfaststorage_native.so"]
+
+
+def _load():
+    here = os.path.dirname(os.path.abspath(__file__))
+    candidates = []
+    env = os.environ.get("FASTSTORAGE_NATIVE_PATH")
+    if env:
+        candidates += [env] if os.path.isfile(env) else [os.path.join(env, n) for n in _lib_names()]
+    for d in (here, os.path.join(here, "..", "target", "release")):
+        candidates += [os.path.join(d, n) for n in _lib_names()]
+    for path in candidates:
+        if os.path.isfile(path):
+            break
+    else:
+        raise FastStorageError("native library not found; tried: " + ", ".join(candidates))
+
+    lib = ctypes.CDLL(path)
+    vp, i32 = ctypes.c_void_p, ctypes.c_int32
+    lib.get_last_error.argtypes     = [ctypes.c_char_p, ctypes.c_size_t]
+    lib.get_last_error.restype      = i32
+    lib.open_reader.argtypes        = [ctypes.c_char_p, ctypes.POINTER(vp)]
+    lib.open_reader.restype         = i32
+    lib.read_message_batch.argtypes = [vp, ctypes.POINTER(vp), ctypes.POINTER(ctypes.c_uint16), ctypes.c_size_t]
+    lib.read_message_batch.restype  = i32
+    lib.set_kind_filter.argtypes    = [vp, ctypes.POINTER(ctypes.c_int16), ctypes.c_size_t]
+    lib.set_kind_filter.restype     = i32
+    lib.close_reader.argtypes       = [vp]
+    lib.close_reader.restype        = None
+    return lib
+
+
+_lib = None
+
+
+def _native():
+    global _lib
+    if _lib is None:
+        _lib = _load()
+    return _lib
+
+
+def _last_error(lib):
+    n = lib.get_last_error(None, 0)
+    if n <= 0:
+        return "unknown error"
+    buf = ctypes.create_string_buffer(n + 1)
+    lib.get_last_error(buf, n + 1)
+    return buf.value.decode("utf-8", "replace")
+
+
+def _decode(raw):
+    kind, _size, time = _HEADER.unpack_from(raw)
+    if kind == KIND_DEPTH:
+        price, volume, flags = _DEPTH.unpack_from(raw)
+        return Depth(time, price / SCALE, volume / SCALE, flags)
+    if kind == KIND_TICK:
+        id_, price, volume, side = _TICK.unpack_from(raw)
+        return Tick(time, id_, price / SCALE, volume / SCALE, side)
+    if kind == KIND_SYMBOL:
+        return Symbol(time, raw[12:].rstrip(b"\0").decode("utf-8", "replace"))
+    if kind == KIND_CANDLE:
+        o, h, l, c, v = _CANDLE.unpack_from(raw)
+        return Candle(time, o / SCALE, h / SCALE, l / SCALE, c / SCALE, v / SCALE)
+    if kind == KIND_CANDLE_END:
+        return CandleEnd(time)
+    raise FastStorageError("unknown message kind %d" % kind)
+
+
+class Reader:
+    """Iterates the messages of one file as namedtuples, in file order."""
+
+    def __init__(self, path):
+        self._lib = _native()
+        self._handle = ctypes.c_void_p()
+        rc = self._lib.open_reader(os.fsencode(path), ctypes.byref(self._handle))
+        if rc != 0:
+            raise FastStorageError(_last_error(self._lib), rc)
+        self._ptrs = (ctypes.c_void_p * _BATCH)()
+        self._sizes = (ctypes.c_uint16 * _BATCH)()
+
+    @classmethod
+    def open(cls, path):
+        return cls(path)
+
+    def set_kinds(self, kinds):
+        """Only yield messages of these kinds (KIND_* values); None for all."""
+        if kinds is None:
+            rc = self._lib.set_kind_filter(self._check(), None, 0)
+        else:
+            arr = (ctypes.c_int16 * len(kinds))(*kinds)
+            rc = self._lib.set_kind_filter(self._check(), arr, len(kinds))
+        if rc != 0:
+            raise FastStorageError("invalid message kind in %r" % (kinds,))
+
+    def raw_batches(self):
+        """Yields lists of raw message bytes (header included), one block at most per list."""
+        while True:
+            n = self._lib.read_message_batch(self._check(), self._ptrs, self._sizes, _BATCH)
+            if n == 0:
+                return
+            if n < 0:
+                raise FastStorageError(_last_error(self._lib), n)
+            ptrs, sizes = self._ptrs, self._sizes
+            yield [ctypes.string_at(ptrs[i], sizes[i]) for i in range(n)]
+
+    def __iter__(self):
+        for batch in self.raw_batches():
+            for raw in batch:
+                yield _decode(raw)
+
+    def close(self):
+        if self._handle:
+            self._lib.close_reader(self._handle)
+            self._handle = ctypes.c_void_p()
+
+    def _check(self):
+        if not self._handle:
+            raise FastStorageError("reader is closed")
+        return self._handle
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def __del__(self):
+        if getattr(self, "_handle", None):
+            self.close()
+
+
+def read_all_ticks(path):
+    """All trades in `path`. With NumPy: a structured array with fields
+    time, id, price, volume, side (prices and volumes scaled). Without it,
+    or if some tick is not the plain 37-byte layout: a list of `Tick`."""
+    with Reader(path) as rdr:
+        rdr.set_kinds([KIND_TICK])
+        raws = [raw for batch in rdr.raw_batches() for raw in batch]
+    try:
+        import numpy as np
+    except ImportError:
+        np = None
+    if np is None or any(len(r) != _TICK_LEN for r in raws):
+        return [_decode(r) for r in raws]
+    src = np.frombuffer(b"".join(raws), dtype=[
+        ("kind", "<i2"), ("size", "<u2"), ("time", "<i8"), ("id", "<i8"),
+        ("price", "<i8"), ("volume", "<i8"), ("side", "u1"),
+    ])
+    out = np.empty(len(src), dtype=[
+        ("time", "<i8"), ("id", "<i8"), ("price", "<f8"), ("volume", "<f8"), ("side", "u1"),
+    ])
+    out["time"], out["id"], out["side"] = src["time"], src["id"], src["side"]
+    out["price"], out["volume"] = src["price"] / SCALE, src["volume"] / SCALE
+    return out
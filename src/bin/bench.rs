@@ -1,92 +1,60 @@
-use std::{
-    collections::BTreeMap,
-    env,
-    ffi::{c_void, CString},
-    ptr,
-    time::Instant,
-};
+use std::{env, time::{Duration, Instant}};
 
 use faststorage_native::*;
-use ordered_float::OrderedFloat;
+use faststorage_native::aggregator::TradeStats;
+use faststorage_native::orderbook::DepthBook;
 
-/* ─────────────── order‑book structs ───────────────────────── */
-
-#[derive(Default)]
-struct DepthBook {
-    bids: BTreeMap<OrderedFloat<f64>, f64>,
-    asks: BTreeMap<OrderedFloat<f64>, f64>,
-}
-impl DepthBook {
-    fn update(&mut self, price: f64, vol: f64, flags: u8) {
-        let mf = MarketFlag::from_bits_truncate(flags);
-        if mf.contains(MarketFlag::CLEAR) {
-            self.bids.clear();
-            self.asks.clear();
-        }
-        let side = if mf.contains(MarketFlag::BUY) { &mut self.bids } else { &mut self.asks };
-        if vol > 0.0 {
-            side.insert(OrderedFloat(price), vol);
-        } else {
-            side.remove(&OrderedFloat(price));
-        }
-    }
-    fn best_bid(&self) -> Option<(f64, f64)> {
-        self.bids.iter().rev().next().map(|(p, v)| (p.0, *v))
-    }
-    fn best_ask(&self) -> Option<(f64, f64)> {
-        self.asks.iter().next().map(|(p, v)| (p.0, *v))
-    }
+// The capture being benchmarked is assumed finished: nothing truncates or
+// rewrites it while mapped.
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn open(path: &str, mmap: bool) -> anyhow::Result<FastCacheReader> {
+    Ok(if mmap { unsafe { FastCacheReader::open_mmap(path)? } } else { FastCacheReader::open(path)? })
 }
 
-#[derive(Default)]
-struct Trades(Vec<(i64, f64, f64)>);
-impl Trades {
-    fn push(&mut self, ts: i64, p: f64, v: f64) { self.0.push((ts, p, v)); }
+#[cfg(not(all(unix, target_pointer_width = "64")))]
+fn open(path: &str, mmap: bool) -> anyhow::Result<FastCacheReader> {
+    anyhow::ensure!(!mmap, "--mmap is not supported on this platform");
+    Ok(FastCacheReader::open(path)?)
 }
 
 fn main() -> anyhow::Result<()> {
-    let path = env::args().nth(1).expect("usage: bench <file.bin.lz4>");
-    println!("Benchmarking {path}");
+    let path = env::args().nth(1).expect("usage: bench <file.bin.lz4> [--mmap] [--progress]");
+    let mmap = env::args().skip(2).any(|a| a == "--mmap");
+    let progress = env::args().skip(2).any(|a| a == "--progress");
+    println!("Benchmarking {path}{}", if mmap { " (mmap)" } else { "" });
     let start = Instant::now();
 
-    /* open native reader */
-    let c_path = CString::new(path)?;
-    let mut h: *mut c_void = ptr::null_mut();
-    anyhow::ensure!(faststorage_native::open_reader(c_path.as_ptr(), &mut h) == 0, "open_reader failed");
+    let mut rdr = open(&path, mmap)?.messages();
 
     let mut depth  = DepthBook::default();
-    let mut trades = Trades::default();
+    let mut trades = TradeStats::new();
     let mut msgs   = 0usize;
     let mut building_snapshot = true;   // block book inspection until first trade after CLEAR
+    let mut last_report = start;
 
-    loop {
-        let mut msg: *const c_void = ptr::null();
-        let sz = unsafe { faststorage_native::read_message(h, &mut msg) };
-        if sz == 0 { break; }
-
-        let kind: i16 = unsafe { ptr::read_unaligned(msg as *const i16) };
-
-        match kind {
-            x if x == MessageKind::Depth as i16 => {
-                let _seq = unsafe { ptr::read_unaligned((msg as *const u8).add(4) as *const i64) };
-                let p  = unsafe { ptr::read_unaligned((msg as *const u8).add(12) as *const i64) } as f64 / 1e8;
-                let v  = unsafe { ptr::read_unaligned((msg as *const u8).add(20) as *const i64) } as f64 / 1e8;
-                let fl = unsafe { ptr::read_unaligned((msg as *const u8).add(28) as *const u8) };
-                depth.update(p, v, fl);
+    while let Some(msg) = rdr.next_message()? {
+        match MessageKind::try_from(msg.kind())? {
+            MessageKind::Depth => {
+                let Some(d) = msg.as_depth() else { continue };
+                depth.apply(&d);
             }
-            x if x == MessageKind::Tick as i16 => {
-                let ts = unsafe { ptr::read_unaligned((msg as *const u8).add(4)  as *const i64) };
-                let p  = unsafe { ptr::read_unaligned((msg as *const u8).add(20) as *const i64) } as f64 / 1e8;
-                let v  = unsafe { ptr::read_unaligned((msg as *const u8).add(28) as *const i64) } as f64 / 1e8;
-                trades.push(ts, p, v);
+            MessageKind::Tick => {
+                let Some(t) = msg.as_tick() else { continue };
+                trades.push(&t);
                 building_snapshot = false;      // snapshot finished
             }
-            _ => {}
+            MessageKind::Symbol | MessageKind::Candle | MessageKind::CandleEnd => {}
         }
         msgs += 1;
-    }
 
-    faststorage_native::close_reader(h);
+        if progress && msgs.is_multiple_of(1 << 16) && last_report.elapsed() >= Duration::from_secs(1) {
+            last_report = Instant::now();
+            let r = rdr.get_ref();
+            let pct = r.file_len().map_or(0.0, |len| r.stream_position() as f64 * 100.0 / len as f64);
+            eprint!("\r{pct:5.1}%  {:.0} msgs/s", msgs as f64 / start.elapsed().as_secs_f64());
+        }
+    }
+    if progress { eprintln!(); }
 
     let dur = start.elapsed();
     println!(
@@ -101,8 +69,8 @@ fn main() -> anyhow::Result<()> {
         {
             println!(
                 "Final OB – levels: bids {}, asks {}  |  best bid {:.2} ({}), best ask {:.2} ({})",
-                depth.bids.len(),
-                depth.asks.len(),
+                depth.bid_levels(),
+                depth.ask_levels(),
                 bb_price,
                 bb_vol,
                 ba_price,
@@ -113,9 +81,14 @@ fn main() -> anyhow::Result<()> {
         println!("Snapshot never completed – no trades encountered in file.");
     }
 
-    println!("Trades captured: {}", trades.0.len());
-    if let Some(t) = trades.0.last() {
-        println!("Last trade: {:?}", t);
+    let trades = trades.finish();
+    println!("Trades captured: {}", trades.trades());
+    if let Some(t) = trades.last {
+        println!("Last trade: {:?}", (t.header.time, t.scaled_price(&Scale::DEFAULT), t.scaled_volume(&Scale::DEFAULT)));
+    }
+    if let Some(vwap) = trades.vwap {
+        println!("VWAP {vwap:.8}  |  buy {:.8} in {}, sell {:.8} in {}",
+            trades.buy_volume, trades.buy_trades, trades.sell_volume, trades.sell_trades);
     }
 
     Ok(())
@@ -0,0 +1,14 @@
+use std::env;
+
+use faststorage_native::*;
+
+/// Prints `stream_digest` per file, `sha256sum`‑style: `<hex>  <path>`.
+fn main() -> anyhow::Result<()> {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    assert!(!paths.is_empty(), "usage: faststorage-digest <file.bin.lz4>...");
+    for path in &paths {
+        let digest = stream_digest(FastCacheReader::open(path)?)?;
+        println!("{digest:016x}  {path}");
+    }
+    Ok(())
+}
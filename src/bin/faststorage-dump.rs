@@ -0,0 +1,14 @@
+use std::{env, io::{self, BufWriter}};
+
+use faststorage_native::*;
+
+fn main() -> anyhow::Result<()> {
+    let path = env::args().nth(1).expect("usage: faststorage-dump <file.bin.lz4>");
+    let out = BufWriter::new(io::stdout().lock());
+
+    match dump_jsonl(FastCacheReader::open(&path)?, out) {
+        // `faststorage-dump file | head` closes the pipe early; that's not an error.
+        Err(FastStorageError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        r => Ok(r.map(drop)?),
+    }
+}
@@ -1,155 +1,3194 @@
 //! FastStorage.Native
+//!
+//! The decoder, wire structs and errors live in `faststorage-core`, which
+//! builds without `std`, and are re‑exported here.
 
 use std::{
+    cell::RefCell,
+    collections::BTreeMap,
     ffi::{c_char, CStr},
-    fs::File,
-    io::{BufReader, Read},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    ops::Range,
     os::raw::c_void,
+    ptr,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
-use bitflags::bitflags;
+pub use faststorage_core::*;
 use byteorder::{ByteOrder, LittleEndian};
 
-/* ────────────────  1. decoder  ────────────── */
+// Fails with [`FastStorageError::Invalid`] unless `cond` holds.
+macro_rules! ensure {
+    ($cond:expr, $($fmt:tt)+) => {
+        if !$cond { return Err(FastStorageError::Invalid(format!($($fmt)+))); }
+    };
+}
 
-mod k4os_pickler {
-    use super::*;
-    use lz4_flex::block;
+/* ────────────────  1. reader implementation  ───────────────────────── */
+
+/// Running totals over the blocks a reader has decompressed.
+/// `compressed` counts the pickled payloads as in the length prefixes,
+/// without the prefixes or checksums themselves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompressionStats {
+    pub blocks:       u64,
+    pub compressed:   u64,
+    pub decompressed: u64,
+}
+
+impl CompressionStats {
+    /// Decompressed bytes per compressed byte; `None` before any block.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.compressed > 0).then(|| self.decompressed as f64 / self.compressed as f64)
+    }
+
+    /// Mean decompressed block length; `None` before any block.
+    pub fn avg_block_len(&self) -> Option<f64> {
+        (self.blocks > 0).then(|| self.decompressed as f64 / self.blocks as f64)
+    }
+}
+
+/// Read‑only private mapping of a whole file, via the platform `mmap`.
+/// If the file is truncated while it is mapped, accessing the lost pages
+/// raises SIGBUS, as with any mapping; hence [`Mmap::map`] is `unsafe`.
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod mmap {
+    use std::{
+        fs::File,
+        io,
+        ops::Deref,
+        os::{
+            raw::{c_int, c_void},
+            unix::io::AsRawFd,
+        },
+        ptr, slice,
+    };
+
+    extern "C" {
+        fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, off: i64) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
 
-    pub fn unpickle(src: &[u8]) -> Result<Vec<u8>> {
-        if src.is_empty() {
-            return Ok(Vec::new());
+    const PROT_READ:   c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+
+    pub struct Mmap { ptr: *mut c_void, len: usize }
+
+    // The mapping is read‑only and owned, so it can move between threads.
+    unsafe impl Send for Mmap {}
+
+    impl Mmap {
+        /// # Safety
+        /// `file` must not be truncated or modified, by this process or any
+        /// other, until the mapping is dropped.
+        pub unsafe fn map(file: &File) -> io::Result<Self> {
+            let len = file.metadata()?.len() as usize;
+            if len == 0 {
+                return Ok(Self { ptr: ptr::null_mut(), len: 0 }); // mmap rejects empty ranges
+            }
+            let ptr = unsafe { mmap(ptr::null_mut(), len, PROT_READ, MAP_PRIVATE, file.as_raw_fd(), 0) };
+            if ptr as isize == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { ptr, len })
         }
+    }
 
-        let b0 = src[0];
-        anyhow::ensure!(b0 & 7 == 0, "unsupported version");
+    impl Deref for Mmap {
+        type Target = [u8];
+        fn deref(&self) -> &[u8] {
+            if self.len == 0 { return &[]; }
+            unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+    }
 
-        let diff_len = match (b0 >> 6) & 3 { 0 => 0, 1 => 1, 2 => 2, _ => 4 };
-        let data_off = 1 + diff_len;
-        anyhow::ensure!(src.len() >= data_off, "header truncated");
+    impl Drop for Mmap {
+        fn drop(&mut self) {
+            if self.len != 0 {
+                unsafe { munmap(self.ptr, self.len) };
+            }
+        }
+    }
+}
 
-        let diff = if diff_len == 0 {
-            0
-        } else {
-            let mut tmp = [0u8; 4];
-            tmp[..diff_len].copy_from_slice(&src[1..data_off]);
-            LittleEndian::read_u32(&tmp) as usize
-        };
+/// A whole file held in memory, owned or mapped.
+enum Bytes {
+    Owned(Vec<u8>),
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    Mapped(mmap::Mmap),
+}
 
-        let payload = &src[data_off..];
-        if diff == 0 {
-            Ok(payload.to_vec())
-        } else {
-            let expected = payload.len() + diff;
-            let out = block::decompress(payload, expected)?;
-            Ok(out)
+impl std::ops::Deref for Bytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Owned(v)  => v,
+            #[cfg(all(unix, target_pointer_width = "64"))]
+            Bytes::Mapped(m) => m,
         }
     }
 }
 
-/* ────────────────  2. wire‑format structs  ─────────────────────────── */
+/// Where compressed blocks come from.
+enum Input<R> {
+    Stream(R),
+    Bytes(Bytes),
+}
+
+impl<R: Read> Input<R> {
+    /// Reads the 4‑byte buffer length that starts every file.
+    fn read_header(&mut self) -> Result<i32> {
+        match self {
+            Input::Stream(f) => {
+                let mut hdr = [0u8; 4];
+                match read_full(f, &mut hdr)? {
+                    0 => Err(FastStorageError::EmptyFile),
+                    4 => Ok(LittleEndian::read_i32(&hdr)),
+                    _ => Err(FastStorageError::HeaderTruncated),
+                }
+            }
+            Input::Bytes(b) => {
+                if b.is_empty() { return Err(FastStorageError::EmptyFile); }
+                if b.len() < 4 { return Err(FastStorageError::HeaderTruncated); }
+                Ok(LittleEndian::read_i32(&b[..4]))
+            }
+        }
+    }
 
-#[repr(i16)]
-#[derive(Clone, Copy)]
-pub enum MessageKind { Depth = 0, Tick = 1, Symbol = 2, Candle = 3, CandleEnd = 4 }
+    /// Reads the length‑prefixed compressed block at `*pos` plus
+    /// `fr.trailer` extra bytes (the checksum, if any); `None` at end of
+    /// input. Streams read into `scratch`, which is reused across blocks;
+    /// mapped input is sliced in place instead of copied.
+    fn read_frame<'a>(
+        &'a mut self, pos: &mut u64, scratch: &'a mut Vec<u8>, fr: Framing,
+    ) -> Result<Option<&'a [u8]>> {
+        let (trailer, endian) = (fr.trailer, fr.endian);
+        match self {
+            Input::Stream(f) => {
+                let mut hdr = [0u8; 4];
+                match read_full(f, &mut hdr)? {
+                    0 => return Ok(None),
+                    4 => {}
+                    n => return Err(TruncatedFile { block_pos: *pos, missing: 4 - n }.into()),
+                }
+                let cmp_len = endian.read_i32(&hdr);
+                if cmp_len <= 0 { return Err(FastStorageError::CorruptBlock(format!("invalid compressed length {cmp_len}"))); }
+                fr.check_len(*pos, cmp_len as usize)?;
 
-bitflags! {
-    #[repr(transparent)]
-    #[derive(Default)]
-    pub struct MarketFlag: u8 {
-        const BUY       = 1;
-        const SELL      = 2;
-        const CLEAR     = 4;
-        const END_OF_TX = 8;
+                // Don't allocate for bytes the file doesn't have.
+                let want = cmp_len as usize + trailer;
+                if let Some(avail) = fr.file_len.map(|len| len.saturating_sub(*pos + 4)).filter(|&a| a < want as u64) {
+                    return Err(TruncatedFile { block_pos: *pos, missing: want - avail as usize }.into());
+                }
+                scratch.resize(want, 0);
+                let n = read_full(f, scratch)?;
+                if n < scratch.len() {
+                    return Err(TruncatedFile { block_pos: *pos, missing: scratch.len() - n }.into());
+                }
+                *pos += (hdr.len() + scratch.len()) as u64;
+                Ok(Some(scratch))
+            }
+            Input::Bytes(map) => {
+                let at = *pos as usize;
+                if at >= map.len() { return Ok(None); }
+                let Some(hdr) = map.get(at..at + 4) else {
+                    return Err(TruncatedFile { block_pos: *pos, missing: at + 4 - map.len() }.into());
+                };
+                let cmp_len = endian.read_i32(hdr);
+                if cmp_len <= 0 { return Err(FastStorageError::CorruptBlock(format!("invalid compressed length {cmp_len}"))); }
+                fr.check_len(*pos, cmp_len as usize)?;
+
+                let end = at + 4 + cmp_len as usize + trailer;
+                if end > map.len() {
+                    return Err(TruncatedFile { block_pos: *pos, missing: end - map.len() }.into());
+                }
+                *pos = end as u64;
+                Ok(Some(&map[at + 4..end]))
+            }
+        }
     }
 }
 
-#[repr(C, packed)]
+/// How [`Input::read_frame`] checks and reads one frame.
 #[derive(Clone, Copy)]
-pub struct MessageHeader { pub kind: i16, pub size: u16, pub time: i64 }
+struct Framing {
+    trailer:  usize,           // checksum bytes after the payload
+    endian:   Endian,
+    max_cmp:  usize,           // largest payload a block within the limit can have
+    file_len: Option<u64>,     // end of the input, when known and not growing
+}
 
-#[repr(C, packed)]
-#[derive(Clone, Copy)]
-pub struct DepthItem { pub header: MessageHeader, pub price: i64, pub volume: i64, pub flags: u8 }
+impl Framing {
+    /// Rejects a length prefix no block within the size limit could have,
+    /// before anything is allocated for it.
+    fn check_len(&self, pos: u64, cmp_len: usize) -> Result<()> {
+        ensure!(
+            cmp_len <= self.max_cmp,
+            "block at byte {pos}: compressed length {cmp_len} exceeds the {} bytes a block within the limit can take \
+             (see set_max_block_len)", self.max_cmp
+        );
+        Ok(())
+    }
+}
 
-#[repr(C, packed)]
-#[derive(Clone, Copy)]
-pub struct TickItem  { pub header: MessageHeader, pub id: i64, pub price: i64, pub volume: i64, pub side: u8 }
+/// Reads until `buf` is full or the stream ends; returns the bytes read.
+fn read_full<R: Read>(f: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match f.read(&mut buf[n..]) {
+            Ok(0)  => break,
+            Ok(k)  => n += k,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
 
-/* ────────────────  3. reader implementation  ───────────────────────── */
+/// Read buffer in front of a file opened by path or passed as a [`File`]:
+/// `BufReader`'s own default. Blocks at least this long are read straight
+/// into the reader's scratch buffer, bypassing it, so on local disks a
+/// bigger buffer saves little; see [`FastCacheReader::open_with_buf_capacity`].
+pub const DEFAULT_READ_BUF_CAPACITY: usize = 8 * 1024;
 
-struct FastCacheReader {
-    file:      BufReader<File>,
+/// Block layer of [`FastCacheReader`]: parses the file header and the
+/// block framing, and decompresses each block into a reused buffer,
+/// checking its CRC if asked to. It knows nothing about messages, so
+/// kind and time filters and `size == 0` terminators don't apply here.
+pub struct BlockReader<R = BufReader<File>> {
+    input:     Input<R>,
     src:       Vec<u8>,
-    offset:    usize,
+    cmp:       Vec<u8>,        // compressed‑block scratch for stream input
     block_len: usize,
+    file_pos:  u64,            // bytes consumed from `input`
+    start:     u64,            // file offset of the first block
+    block_pos: u64,            // file offset of the current block's length prefix
+    lenient:   bool,
+    errors:    Vec<FastStorageError>,
+    skipped:   u64,            // blocks dropped in lenient mode
+    max_block: usize,
+    checksums: bool,           // file carries a CRC‑32 after every block
+    verify:    bool,
+    blocks:    u64,            // frames read so far
+    buf_len:   usize,          // as declared in the file header
+    file_len:  Option<u64>,
+    on_block:  Option<Box<dyn FnMut(u64, u64) + Send>>,
+    follow:    Option<SeekFn<R>>,  // set in follow mode, to back up over a partial frame
+    endian:    Endian,
+    stats:     CompressionStats,
+}
+
+/// Message reader over any byte stream; `open` gives the buffered‑file
+/// flavour used by the C ABI. Framing and decompression are left to a
+/// [`BlockReader`]; this layer walks the messages inside each block.
+///
+/// The reader is `Send` whenever `R` is, so it can be opened on one thread
+/// and handed to another. It is not `Sync`: every read mutates it, so
+/// sharing one between threads needs external locking.
+///
+/// A message header with `size == 0` is an explicit terminator: it ends
+/// the stream for good, and whatever follows it in the block or the file
+/// is never read. Later calls keep returning `None` until `rewind`.
+/// [`MultiFileReader`] treats it as the end of that file only.
+///
+/// Trailing bytes too few for a header end their block quietly. A header
+/// whose `size` runs past the block is a [`PartialMessage`] error, or in
+/// lenient mode drops the rest of the block.
+pub struct FastCacheReader<R = BufReader<File>> {
+    inner:     BlockReader<R>,
+    offset:    usize,
+    last:      Option<MessageHeader>,
+    last_at:   (u64, usize),
+    last_block: u64,           // index of the block `last` came from
+    symbols:   SymbolDecoding,
+    index:     Option<(BlockIndex, SeekFn<R>)>,
+    kinds:     Option<u32>,    // bit per raw kind that passes the filter
+    times:     Option<Range<i64>>,
+    exact:     bool,           // sizes must equal `MessageKind::wire_size`
+    ended:     bool,           // hit a `size == 0` terminator
+    strict_time: bool,
+    out_of_order: u64,         // backward steps let through in lenient mode
+}
+
+type SeekFn<R> = fn(&mut R, SeekFrom) -> std::io::Result<u64>;
+
+// Readers and writers must stay movable across threads.
+const _: () = {
+    fn send<T: Send>() {}
+    let _ = send::<BlockReader>;
+    let _ = send::<FastCacheReader>;
+    let _ = send::<MessageIter>;
+    let _ = send::<BlockIter>;
+    let _ = send::<ParBlocks>;
+    let _ = send::<PacedReader>;
+    let _ = send::<FastCacheWriter>;
+};
+
+impl BlockReader {
+    pub fn open(path: &str) -> Result<Self> {
+        let f = File::open(path).with_context(|| format!("open {path}"))?;
+        Self::from_file(f)
+    }
+
+    /// See [`FastCacheReader::open_with_buf_capacity`].
+    pub fn open_with_buf_capacity(path: &str, capacity: usize) -> Result<Self> {
+        let f = File::open(path).with_context(|| format!("open {path}"))?;
+        Self::buffered(f, capacity)
+    }
+
+    /// See [`FastCacheReader::from_file`].
+    pub fn from_file(f: File) -> Result<Self> { Self::buffered(f, DEFAULT_READ_BUF_CAPACITY) }
+
+    fn buffered(f: File, capacity: usize) -> Result<Self> {
+        let meta = f.metadata()?;
+        let mut rdr = Self::new(Input::Stream(BufReader::with_capacity(capacity, f)))?;
+        rdr.file_len = meta.is_file().then_some(meta.len());
+        Ok(rdr)
+    }
+}
+
+impl<R: Read> BlockReader<R> {
+    /// Reads a file image from any stream, as [`FastCacheReader::from_reader`].
+    pub fn from_reader(rdr: R) -> Result<Self> {
+        Self::new(Input::Stream(rdr))
+    }
+
+    fn new(mut input: Input<R>) -> Result<Self> {
+        let raw = input.read_header()?;
+        if raw == ZSTD_MAGIC {
+            let msg = "file is zstd‑compressed; decompress it first (`zstd -d`), or stream it through a decoder into FastCacheReader::from_reader";
+            return Err(FastStorageError::CorruptBlock(msg.into()));
+        }
+        Self::with_header(input, raw, 4)
+    }
+
+    /// `raw` is the file‑header word; blocks start at byte `start`.
+    fn with_header(input: Input<R>, raw: i32, start: u64) -> Result<Self> {
+        let checksums = raw & CHECKSUM_FLAG != 0;
+        let buf_len = raw & !CHECKSUM_FLAG;
+        if buf_len == 0 { return Err(FastStorageError::CorruptBlock("invalid buffer length in file".into())); }
+        Ok(Self {
+            input, src: Vec::new(), cmp: Vec::new(), block_len: 0,
+            file_pos: start, start, block_pos: 0, lenient: false, errors: Vec::new(), skipped: 0,
+            max_block: DEFAULT_MAX_BLOCK_LEN, checksums, verify: false, blocks: 0,
+            buf_len: buf_len as usize, file_len: None, on_block: None, follow: None, endian: Endian::Little,
+            stats: CompressionStats::default(),
+        })
+    }
+
+    /// Decompresses the next block. Its bytes stay valid until the next call.
+    pub fn next_block(&mut self) -> Result<Option<BlockRef<'_>>> {
+        if !self.load()? { return Ok(None); }
+        let trailer = if self.checksums { 4 } else { 0 };
+        Ok(Some(BlockRef {
+            index:          self.blocks - 1,
+            file_pos:       self.block_pos,
+            compressed_len: (self.file_pos - self.block_pos) as usize - 4 - trailer,
+            bytes:          &self.src[..self.block_len],
+        }))
+    }
+
+    /// Buffer length declared in the file header.
+    pub fn buffer_len(&self) -> usize { self.buf_len }
+
+    /// See [`FastCacheReader::reserve`].
+    pub fn reserve(&mut self, capacity: usize) {
+        if capacity > self.src.len() { self.src.resize(capacity, 0); }
+        if matches!(self.input, Input::Stream(_)) { self.cmp.reserve(capacity); }
+    }
+
+    /// See [`FastCacheReader::stream_position`].
+    pub fn stream_position(&self) -> u64 { self.file_pos }
+
+    /// Total file size, for progress reporting; `None` for plain streams.
+    pub fn file_len(&self) -> Option<u64> { self.file_len }
+
+    /// See [`FastCacheReader::set_on_block`].
+    pub fn set_on_block(&mut self, f: impl FnMut(u64, u64) + Send + 'static) {
+        self.on_block = Some(Box::new(f));
+    }
+
+    /// See [`FastCacheReader::set_lenient`].
+    pub fn set_lenient(&mut self, lenient: bool) { self.lenient = lenient; }
+
+    /// See [`FastCacheReader::set_max_block_len`].
+    pub fn set_max_block_len(&mut self, max: usize) { self.max_block = max; }
+
+    /// See [`FastCacheReader::set_verify_checksums`].
+    pub fn set_verify_checksums(&mut self, verify: bool) { self.verify = verify; }
+
+    /// Whether the file was written with per‑block checksums.
+    pub fn has_checksums(&self) -> bool { self.checksums }
+
+    /// Errors swallowed so far in lenient mode, oldest first.
+    pub fn errors(&self) -> &[FastStorageError] { &self.errors }
+
+    /// Blocks skipped so far in lenient mode.
+    pub fn skipped_blocks(&self) -> u64 { self.skipped }
+
+    /// See [`FastCacheReader::compression_stats`].
+    pub fn compression_stats(&self) -> CompressionStats { self.stats }
+
+    fn block(&self) -> &[u8] { &self.src[..self.block_len] }
+
+    // Checked when the first block is read rather than on open, so that
+    // `set_max_block_len` can still raise the limit; the block buffer is
+    // only allocated once this passes.
+    fn check_buffer_len(&self) -> Result<()> {
+        if self.buf_len > self.max_block {
+            let swapped = (self.header_word().swap_bytes() & !CHECKSUM_FLAG) as usize;
+            let hint = if self.start != 0 && swapped > 0 && swapped <= self.max_block {
+                format!("; byte‑swapped it would be {swapped}, so the file may be big‑endian (see set_byte_order)")
+            } else {
+                String::new()
+            };
+            return Err(FastStorageError::BlockTooLarge { len: self.buf_len, limit: self.max_block }
+                .context(format!("file header declares a buffer above the limit (see set_max_block_len){hint}")));
+        }
+        Ok(())
+    }
+
+    fn framing(&self) -> Framing {
+        Framing {
+            trailer:  if self.checksums { 4 } else { 0 },
+            endian:   self.endian,
+            max_cmp:  k4os_pickler::max_pickled_len(self.max_block),
+            // A followed file keeps growing past the length seen at open.
+            file_len: if self.follow.is_some() { None } else { self.file_len },
+        }
+    }
+
+    /// The header word as read, rebuilt from the parsed fields.
+    fn header_word(&self) -> i32 {
+        self.buf_len as i32 | if self.checksums { CHECKSUM_FLAG } else { 0 }
+    }
+
+    /// See [`FastCacheReader::set_byte_order`].
+    pub fn set_byte_order(&mut self, endian: Endian) -> Result<()> {
+        ensure!(self.blocks == 0, "byte order must be set before the first block is read");
+        if endian == self.endian { return Ok(()); }
+        if self.start != 0 {
+            let raw = self.header_word().swap_bytes();
+            let buf_len = raw & !CHECKSUM_FLAG;
+            if buf_len == 0 { return Err(FastStorageError::CorruptBlock("invalid buffer length in file".into())); }
+            self.checksums = raw & CHECKSUM_FLAG != 0;
+            self.buf_len   = buf_len as usize;
+        }
+        self.endian = endian;
+        Ok(())
+    }
+
+    fn load(&mut self) -> Result<bool> {
+        self.check_buffer_len()?;
+        if self.src.len() < self.buf_len { self.src.resize(self.buf_len, 0); }
+        let trailer = if self.checksums { 4 } else { 0 };
+        loop {
+            let start = self.file_pos;
+            let fr = self.framing();
+            let frame = match self.input.read_frame(&mut self.file_pos, &mut self.cmp, fr) {
+                Ok(Some(buf))          => buf,
+                Ok(None)               => return Ok(false),
+                Err(FastStorageError::Truncated(_)) if self.follow.is_some() => {
+                    if let (Input::Stream(r), Some(seek)) = (&mut self.input, self.follow) {
+                        seek(r, SeekFrom::Start(start))?;
+                    }
+                    self.file_pos = start;
+                    return Ok(false);
+                }
+                Err(e) if self.lenient => {
+                    self.errors.push(e.context(format!("block at byte {start}")));
+                    return Ok(false);
+                }
+                Err(e)                 => return Err(e),
+            };
+            let index = self.blocks;
+            self.blocks += 1;
+
+            let (cmp_buf, crc) = frame.split_at(frame.len() - trailer);
+            let decoded = decode_block(cmp_buf, &mut self.src, self.max_block).and_then(|len| {
+                if self.verify && self.checksums { verify_block(index, crc, &self.src[..len], self.endian)?; }
+                if self.endian == Endian::Big { swap_block(&mut self.src[..len]); }
+                Ok(len)
+            });
+            match decoded {
+                Ok(len) => {
+                    self.block_len = len;
+                    self.block_pos = start;
+                    self.stats.blocks       += 1;
+                    self.stats.compressed   += cmp_buf.len() as u64;
+                    self.stats.decompressed += len as u64;
+                    if let Some(f) = &mut self.on_block { f(self.blocks, self.file_pos); }
+                    return Ok(true);
+                }
+                Err(e) if self.lenient => {
+                    self.errors.push(e.context(format!("block at byte {start}")));
+                    self.skipped += 1;
+                }
+                Err(e)                 => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> BlockReader<R> {
+    /// See [`FastCacheReader::set_follow`].
+    pub fn set_follow(&mut self, follow: bool) { self.follow = follow.then_some(R::seek); }
+
+    /// Restarts from the first block, dropping errors recorded in lenient mode.
+    pub fn rewind(&mut self) -> Result<()> {
+        self.jump(self.start, 0, R::seek)?;
+        self.block_pos = 0;
+        self.errors.clear();
+        self.skipped = 0;
+        self.stats   = CompressionStats::default();
+        Ok(())
+    }
+}
+
+impl<R> BlockReader<R> {
+    /// Continues at the frame at `pos`, the `blocks`‑th in the file.
+    fn jump(&mut self, pos: u64, blocks: u64, seek: SeekFn<R>) -> Result<()> {
+        if let Input::Stream(r) = &mut self.input { seek(r, SeekFrom::Start(pos))?; }
+        self.file_pos  = pos;
+        self.block_len = 0;
+        self.blocks    = blocks;
+        Ok(())
+    }
 }
 
 impl FastCacheReader {
-    fn open(path: &str) -> Result<Self> {
-        let mut f = BufReader::new(File::open(path).with_context(|| format!("open {path}"))?);
-        let mut hdr = [0u8; 4];
-        f.read_exact(&mut hdr)?;
-        let buf_len = LittleEndian::read_i32(&hdr);
-        anyhow::ensure!(buf_len > 0, "invalid buffer length in file");
-        Ok(Self { file: f, src: vec![0; buf_len as usize], offset: 0, block_len: 0 })
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self::from_block_reader(BlockReader::open(path)?))
+    }
+
+    /// Reads an already open file from its current offset, which should be
+    /// the start of the file: `rewind` seeks to absolute byte 4. For pipes
+    /// and other non‑regular files `file_len` stays unknown.
+    pub fn from_file(f: File) -> Result<Self> {
+        Ok(Self::from_block_reader(BlockReader::from_file(f)?))
+    }
+
+    /// [`FastCacheReader::open`] with buffers pre‑sized for blocks of up
+    /// to `capacity` bytes; see [`FastCacheReader::reserve`].
+    pub fn open_with_capacity(path: &str, capacity: usize) -> Result<Self> {
+        let mut rdr = Self::open(path)?;
+        rdr.reserve(capacity);
+        Ok(rdr)
+    }
+
+    /// [`FastCacheReader::open`] with a `capacity`‑byte `BufReader` in front
+    /// of the file instead of [`DEFAULT_READ_BUF_CAPACITY`]. A larger read
+    /// buffer means fewer, bigger `read` calls, which pays off on spinning
+    /// disks and network filesystems; 1 MiB is a good start there. This is
+    /// the raw file buffer only: decompressed blocks live in a separate
+    /// buffer, sized by the file header and by `open_with_capacity`.
+    pub fn open_with_buf_capacity(path: &str, capacity: usize) -> Result<Self> {
+        Ok(Self::from_block_reader(BlockReader::open_with_buf_capacity(path, capacity)?))
+    }
+
+    /// Opens a file written without the leading buffer‑length word, as
+    /// some older capture tools did: blocks start at byte 0, and
+    /// `buffer_len` stands in for the missing header. Such files carry no
+    /// checksums. The two framings can't be told apart reliably from the
+    /// bytes, so there is no automatic detection.
+    pub fn open_headerless(path: &str, buffer_len: usize) -> Result<Self> {
+        ensure!(buffer_len > 0 && buffer_len < i32::MAX as usize, "invalid buffer length {buffer_len}");
+        let f = File::open(path).with_context(|| format!("open {path}"))?;
+        let len = f.metadata().with_context(|| format!("stat {path}"))?.len();
+        let mut blocks = BlockReader::with_header(Input::Stream(BufReader::new(f)), buffer_len as i32, 0)?;
+        blocks.file_len = Some(len);
+        Ok(Self::from_block_reader(blocks))
+    }
+
+    /// Decodes a file image already in memory (e.g. `include_bytes!` or a
+    /// download), exactly as [`FastCacheReader::open`] would. Without
+    /// `std`, [`SliceReader::from_bytes`] reads a borrowed image instead.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let len = data.len() as u64;
+        let mut blocks = BlockReader::new(Input::Bytes(Bytes::Owned(data)))?;
+        blocks.file_len = Some(len);
+        Ok(Self::from_block_reader(blocks))
+    }
+
+    /// Like [`FastCacheReader::open`], but maps the file into memory so
+    /// compressed blocks are decoded straight from the mapping.
+    ///
+    /// # Safety
+    /// The file must not be truncated or modified, by this process or any
+    /// other, while the reader exists: decoding reads the mapping as a plain
+    /// byte slice, and pages lost to truncation raise SIGBUS. So no follow
+    /// mode over a mapping, and no [`FastCacheWriter::append`] to a mapped
+    /// file; use [`FastCacheReader::open`] for files still being written.
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    pub unsafe fn open_mmap(path: &str) -> Result<Self> {
+        let f = File::open(path).with_context(|| format!("open {path}"))?;
+        let map = mmap::Mmap::map(&f).with_context(|| format!("mmap {path}"))?;
+        let len = map.len() as u64;
+        let mut blocks = BlockReader::new(Input::Bytes(Bytes::Mapped(map)))?;
+        blocks.file_len = Some(len);
+        Ok(Self::from_block_reader(blocks))
+    }
+
+    /// Opens `path` together with its [`BlockIndex`] so `seek_to_time` can
+    /// jump straight to the right block. The sidecar is reused while it
+    /// matches the file, and rebuilt otherwise; see
+    /// [`BlockIndex::load_or_build`].
+    pub fn open_with_index(path: &str) -> Result<Self> {
+        let rdr = Self::open(path)?;
+        Ok(rdr.with_index(BlockIndex::load_or_build(path)?))
+    }
+}
+
+impl<R: Read> FastCacheReader<R> {
+    /// Reads a file image from any stream: a socket, `stdin`, a decoder,
+    /// a test cursor. A stream ending mid‑block is an error; only ending
+    /// on a block boundary is a clean end of stream.
+    pub fn from_reader(rdr: R) -> Result<Self> {
+        Ok(Self::from_block_reader(BlockReader::from_reader(rdr)?))
+    }
+
+    /// Reads messages from the blocks `blocks` yields, starting at its
+    /// next block; block‑level settings made on it carry over.
+    pub fn from_block_reader(blocks: BlockReader<R>) -> Self {
+        Self {
+            inner: blocks, offset: 0, last: None, last_at: (0, 0), last_block: 0, symbols: SymbolDecoding::default(),
+            index: None, kinds: None, times: None, exact: false, ended: false, strict_time: false, out_of_order: 0,
+        }
+    }
+
+    /// `(block_byte_offset, in_block_offset)` of the message last returned:
+    /// the file offset of its block's length prefix and its offset inside
+    /// the decompressed block. `(0, 0)` before the first message.
+    pub fn current_message_location(&self) -> (u64, usize) {
+        self.last_at
+    }
+
+    /// Block of the message last returned; `None` before the first message.
+    pub fn current_block(&self) -> Option<BlockPosition> {
+        self.last?;
+        Some(BlockPosition { block_index: self.last_block, first_in_block: self.last_at.1 == 0 })
+    }
+
+    /// Buffer length declared in the file header.
+    pub fn buffer_len(&self) -> usize { self.inner.buf_len }
+
+    /// Pre‑sizes the block buffers for blocks of up to `capacity` bytes, so
+    /// files whose blocks outgrow the header's buffer length don't
+    /// reallocate while reading. Purely a performance hint: buffers still
+    /// grow on demand, and 0 (or anything below the current size) is a no‑op.
+    pub fn reserve(&mut self, capacity: usize) { self.inner.reserve(capacity); }
+
+    /// Bytes of the file consumed so far: the header plus every block
+    /// loaded. Read‑ahead inside a `BufReader` is not counted.
+    pub fn stream_position(&self) -> u64 { self.inner.file_pos }
+
+    /// Total file size, for progress reporting; `None` for plain streams.
+    pub fn file_len(&self) -> Option<u64> { self.inner.file_len }
+
+    /// Called after each block is loaded with `(blocks_read, bytes_consumed)`,
+    /// the latter as in [`FastCacheReader::stream_position`].
+    pub fn set_on_block(&mut self, f: impl FnMut(u64, u64) + Send + 'static) {
+        self.inner.set_on_block(f);
+    }
+
+    /// Consumes the reader into a zero‑copy message iterator.
+    pub fn messages(self) -> MessageIter<R> { MessageIter { rdr: self } }
+
+    /// Consumes the reader into an iterator over whole decompressed blocks,
+    /// starting at the next unread one; the rest of a partially consumed
+    /// block is dropped.
+    pub fn blocks(mut self) -> BlockIter<R> {
+        self.offset = self.inner.block_len;
+        BlockIter { rdr: self }
+    }
+
+    /// Reads the next message and decodes it into a [`Message`]. Unknown
+    /// kinds and messages too short for their struct are errors.
+    pub fn next_typed(&mut self) -> Result<Option<Message>> {
+        let Some(r) = self.next_range()? else { return Ok(None) };
+        let msg = unsafe { MessageRef::from_framed(&self.inner.src[r]) };
+        if self.exact { msg.check_size()?; }
+        msg.decode_with(self.symbols).map(Some)
+    }
+
+    /// The next `n` messages, decoded as by `next_typed`; fewer only at the
+    /// end of the stream or of the time range. The reader is left just
+    /// past the last one, so repeated calls page through the file.
+    pub fn read_up_to(&mut self, n: usize) -> Result<Vec<Message>> {
+        let mut out = Vec::with_capacity(n.min(4096));
+        while out.len() < n {
+            let Some(msg) = self.next_typed()? else { break };
+            out.push(msg);
+        }
+        Ok(out)
     }
 
     unsafe fn next_msg(&mut self) -> Result<Option<*const c_void>> {
-        if self.offset >= self.block_len && !self.load_block()? {
-            return Ok(None);
+        Ok(self.next_range()?.map(|r| self.inner.src.as_ptr().add(r.start) as *const c_void))
+    }
+
+    /// Advances to the first message with `header.time >= ts` without
+    /// consuming it; `false` if the stream ends first. Blocks are
+    /// compressed independently, so this decompresses and scans forward
+    /// from the current position. With a [`BlockIndex`] attached it first
+    /// jumps to the last block starting before `ts`, if that lies ahead.
+    pub fn seek_to_time(&mut self, ts: i64) -> Result<bool> {
+        if let Some((index, seek)) = &self.index {
+            let i = index.entries.partition_point(|e| e.first_time < ts).saturating_sub(1);
+            if let Some(e) = index.entries.get(i).filter(|e| !self.ended && e.file_pos >= self.inner.file_pos) {
+                self.inner.jump(e.file_pos, i as u64, *seek)?;
+                self.offset = 0;
+            }
+        }
+        while let Some(h) = self.peek_header()? {
+            if h.time >= ts { return Ok(true); }
+            if let Err(e) = self.take_message() { self.recover(e)?; }
+        }
+        Ok(false)
+    }
+
+    /// Header of the next message, loading blocks as needed, without
+    /// consuming it. `None` at end of stream.
+    fn peek_header(&mut self) -> Result<Option<MessageHeader>> {
+        while self.inner.block_len - self.offset < size_of::<MessageHeader>() {
+            if !self.load_block()? { return Ok(None); }
+        }
+        let h = self.header_at(self.offset);
+        if h.size == 0 { self.take_message()?; return Ok(None); }
+        Ok(Some(h))
+    }
+
+    /// Header of the message the next `next_typed` (or `next_message`)
+    /// will return, honouring kind and time filters, without consuming it.
+    /// May load the next block; the message stays put either way.
+    pub fn peek(&mut self) -> Result<Option<&MessageHeader>> {
+        let Some(r) = self.advance()? else { return Ok(None) };
+        self.offset = r.start;
+        // `MessageHeader` is packed (align 1) and `take_message` checked
+        // that a whole header lies inside the block.
+        Ok(Some(unsafe { &*(self.inner.src.as_ptr().add(r.start) as *const MessageHeader) }))
+    }
+
+    /// Advances past the next message and returns its byte range in `src`.
+    fn next_range(&mut self) -> Result<Option<Range<usize>>> {
+        let r = self.advance()?;
+        self.mark(r)
+    }
+
+    /// `next_range` without recording the message as handed out.
+    /// Fewer than a header's worth of trailing bytes end the block.
+    fn advance(&mut self) -> Result<Option<Range<usize>>> {
+        loop {
+            if self.inner.block_len - self.offset < size_of::<MessageHeader>() {
+                if !self.load_block()? { return Ok(None); }
+                continue;
+            }
+            match self.take_message() {
+                Ok(Some(r)) if self.past_end(&r) => { self.offset = r.start; return Ok(None); }
+                Ok(Some(r)) if !self.wanted(&r)  => continue,
+                Ok(r)                            => return Ok(r),
+                Err(e)                           => self.recover(e)?,
+            }
+        }
+    }
+
+    /// Like `next_range`, but stops at the end of the current block instead
+    /// of loading the next one.
+    fn next_range_in_block(&mut self) -> Result<Option<Range<usize>>> {
+        loop {
+            if self.inner.block_len - self.offset < size_of::<MessageHeader>() { return Ok(None); }
+            match self.take_message() {
+                Ok(Some(r)) if self.past_end(&r) => { self.offset = r.start; return Ok(None); }
+                Ok(Some(r)) if !self.wanted(&r)  => continue,
+                Ok(r)                            => return self.mark(r),
+                Err(e)                           => { self.recover(e)?; return Ok(None); }
+            }
+        }
+    }
+
+    fn wanted(&self, r: &Range<usize>) -> bool {
+        let MessageHeader { kind, time, .. } = self.header_at(r.start);
+        if self.times.as_ref().is_some_and(|t| time < t.start) { return false; }
+        let Some(mask) = self.kinds else { return true };
+        (0..32).contains(&kind) && mask & (1 << kind) != 0
+    }
+
+    fn past_end(&self, r: &Range<usize>) -> bool {
+        self.times.as_ref().is_some_and(|t| self.header_at(r.start).time >= t.end)
+    }
+
+    /// Records `r` as the message handed to the caller, checking its time
+    /// against the previous one in strict‑time mode.
+    fn mark(&mut self, r: Option<Range<usize>>) -> Result<Option<Range<usize>>> {
+        if let Some(r) = &r {
+            let (previous, h) = (self.last.map(|h| h.time), self.header_at(r.start));
+            self.last_at = (self.inner.block_pos, r.start);
+            self.last_block = self.inner.blocks - 1;
+            self.last = Some(h);
+            if let Some(previous) = previous.filter(|&p| self.strict_time && h.time < p) {
+                if !self.inner.lenient { return Err(OutOfOrderTime { block: self.last_block, previous, time: h.time }.into()); }
+                self.out_of_order += 1;
+            }
         }
-        let ptr = self.src.as_ptr().add(self.offset);
-        let h = &*(ptr as *const MessageHeader);
-        if h.size == 0 { return Ok(None); }
-        self.offset += h.size as usize;
-        Ok(Some(ptr as *const c_void))
+        Ok(r)
+    }
+
+    /// In lenient mode, records a bad message and drops the rest of its
+    /// block; otherwise passes the error through.
+    fn recover(&mut self, e: FastStorageError) -> Result<()> {
+        if !self.inner.lenient { return Err(e); }
+        self.inner.errors.push(e.context(format!("block at byte {}", self.inner.block_pos)));
+        self.inner.skipped += 1;
+        self.offset = self.inner.block_len;
+        Ok(())
+    }
+
+    fn header_at(&self, at: usize) -> MessageHeader {
+        debug_assert!(at + size_of::<MessageHeader>() <= self.inner.block_len);
+        unsafe { ptr::read_unaligned(self.inner.src.as_ptr().add(at) as *const MessageHeader) }
+    }
+
+    /// Validates the header at `offset` against the block and steps past it.
+    fn take_message(&mut self) -> Result<Option<Range<usize>>> {
+        let Some(r) = message_range(self.inner.block(), self.offset)? else {
+            self.ended  = true;
+            self.offset = self.inner.block_len;
+            return Ok(None);
+        };
+        self.offset = r.end;
+        Ok(Some(r))
+    }
+
+    /// Salvage what can be read from a damaged file. In lenient mode a
+    /// block that fails to decompress or checksum is skipped, and a bad
+    /// message drops the rest of its block; reading resumes at the next
+    /// length prefix, and [`skipped_blocks`](Self::skipped_blocks) counts
+    /// either case. Unreadable framing (a bad or truncated length prefix)
+    /// leaves nothing to resync on, so it ends the stream. Everything is
+    /// recorded in `errors()` instead of being returned.
+    pub fn set_lenient(&mut self, lenient: bool) { self.inner.set_lenient(lenient); }
+
+    /// Make `next_typed` reject messages whose `header.size` differs from
+    /// their kind's [`MessageKind::wire_size`]. Off by default, which
+    /// accepts longer messages and ignores the extra bytes.
+    pub fn set_strict_sizes(&mut self, strict: bool) { self.exact = strict; }
+
+    /// Fail with [`OutOfOrderTime`] when a message's `header.time` is below
+    /// that of the message handed out before it, across blocks too. Only
+    /// messages that pass the kind and time filters are compared, and the
+    /// backward one still counts as read, so the next call goes on after it.
+    /// In lenient mode such steps are counted by
+    /// [`out_of_order_times`](Self::out_of_order_times) instead. Off by
+    /// default: the format allows any order, and some feeds interleave.
+    pub fn set_strict_time(&mut self, strict: bool) { self.strict_time = strict; }
+
+    /// Backward time steps let through so far in lenient strict‑time mode.
+    /// Reset by `rewind`.
+    pub fn out_of_order_times(&self) -> u64 { self.out_of_order }
+
+    /// Policy for symbol names that are not valid UTF‑8 (strict by default).
+    pub fn set_symbol_decoding(&mut self, decoding: SymbolDecoding) { self.symbols = decoding; }
+
+    /// Blocks bigger than the file's declared buffer length grow the buffer;
+    /// anything above `max` is rejected as corrupt, and so is a file header
+    /// declaring a buffer length above it. Defaults to
+    /// [`DEFAULT_MAX_BLOCK_LEN`].
+    pub fn set_max_block_len(&mut self, max: usize) { self.inner.set_max_block_len(max); }
+
+    /// Recompute each block's CRC‑32 and fail on mismatch. Only has an
+    /// effect on files written with checksums; off by default.
+    pub fn set_verify_checksums(&mut self, verify: bool) { self.inner.set_verify_checksums(verify); }
+
+    /// Whether the file was written with per‑block checksums.
+    pub fn has_checksums(&self) -> bool { self.inner.checksums }
+
+    /// Read the file as `endian` (little‑endian by default): the header
+    /// word is reinterpreted, and every length prefix, checksum and known
+    /// message field after it is read that way, so messages come out with
+    /// native values. Must be called before the first read. A big‑endian
+    /// file read as little‑endian typically fails on its first length
+    /// prefix; when the header word alone gives it away, the error says so.
+    pub fn set_byte_order(&mut self, endian: Endian) -> Result<()> { self.inner.set_byte_order(endian) }
+
+    /// Only hand out messages of these kinds; the rest are stepped over
+    /// inside the reader. `seek_to_time` still considers every message.
+    pub fn set_kind_filter(&mut self, kinds: &[MessageKind]) {
+        self.kinds = Some(kinds.iter().fold(0, |m, &k| m | 1 << k as i16));
     }
 
+    pub fn clear_kind_filter(&mut self) { self.kinds = None; }
+
+    /// Only hand out messages with `start <= header.time < end`: earlier
+    /// ones are stepped over, and the first message at or past `end` ends
+    /// the stream without being consumed (widen or clear the range to go
+    /// on from it). Call `seek_to_time(start)` first to skip whole blocks.
+    ///
+    /// The format does not require times to be non‑decreasing; the writer
+    /// keeps whatever order it is given. With out‑of‑order input the stop
+    /// at `end` is best effort: later messages inside the range are not seen.
+    pub fn set_time_range(&mut self, times: Range<i64>) { self.times = Some(times); }
+
+    pub fn clear_time_range(&mut self) { self.times = None; }
+
+    /// Errors swallowed so far in lenient mode, oldest first.
+    pub fn errors(&self) -> &[FastStorageError] { &self.inner.errors }
+
+    /// Blocks skipped whole or cut short so far in lenient mode.
+    pub fn skipped_blocks(&self) -> u64 { self.inner.skipped }
+
+    /// Sizes of the blocks decompressed so far; blocks skipped by
+    /// `seek_to_time` or in lenient mode don't count. Reset by `rewind`.
+    pub fn compression_stats(&self) -> CompressionStats { self.inner.stats }
+
     fn load_block(&mut self) -> Result<bool> {
-        let mut hdr = [0u8; 4];
-        if self.file.read_exact(&mut hdr).is_err() { return Ok(false); }
-        let cmp_len = LittleEndian::read_i32(&hdr) as usize;
-        anyhow::ensure!(cmp_len > 0, "compressed length 0");
-
-        let mut cmp_buf = vec![0u8; cmp_len];
-        self.file.read_exact(&mut cmp_buf)?;
-        let block = k4os_pickler::unpickle(&cmp_buf)?;
-        anyhow::ensure!(block.len() <= self.src.len(), "block larger than buffer");
-        self.src[..block.len()].copy_from_slice(&block);
-        self.block_len = block.len();
+        if self.ended || !self.inner.load()? { return Ok(false); }
         self.offset = 0;
         Ok(true)
     }
 }
 
-/* ────────────────  4. C‑ABI exports  ───────────────────────────────── */
+impl<R: Read + Seek> FastCacheReader<R> {
+    /// Restarts from the first block (byte 4, just past the file header,
+    /// or 0 for headerless files) without reopening. Any partially consumed
+    /// block is dropped, and so are errors recorded in lenient mode.
+    pub fn rewind(&mut self) -> Result<()> {
+        self.inner.rewind()?;
+        self.offset  = 0;
+        self.last    = None;
+        self.last_at = (0, 0);
+        self.ended   = false;
+        self.out_of_order = 0;
+        Ok(())
+    }
 
-#[no_mangle]
-pub extern "C" fn open_reader(path: *const c_char, out: *mut *mut c_void) -> i32 {
-    if path.is_null() || out.is_null() { return -1; }
-    let path = unsafe { CStr::from_ptr(path) }.to_string_lossy().into_owned();
-    match FastCacheReader::open(&path) {
-        Ok(r)  => { unsafe { *out = Box::into_raw(Box::new(r)) as *mut _ }; 0 }
-        Err(_) => -1,
+    /// Attaches a block index so [`FastCacheReader::seek_to_time`] can
+    /// skip blocks without decompressing them.
+    pub fn with_index(mut self, index: BlockIndex) -> Self {
+        self.index = Some((index, R::seek));
+        self
     }
+
+    /// Follow a file another process is still writing, like `tail -f`. At
+    /// the end of the data `None` then means "nothing more yet" rather than
+    /// the end of the stream: call again later to pick up new blocks. A
+    /// block the writer has only partly written (a short length prefix or
+    /// payload) is backed over and re‑read in full on a later call instead
+    /// of being reported as [`TruncatedFile`]. A `size == 0` terminator
+    /// still ends the stream. Needs stream input; memory‑mapped and
+    /// in‑memory readers never see bytes added after they were opened.
+    /// The writer side is [`FastCacheWriter::flush`].
+    pub fn set_follow(&mut self, follow: bool) { self.inner.set_follow(follow); }
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn read_message(handle: *mut c_void, out: *mut *const c_void) -> i32 {
-    if handle.is_null() || out.is_null() { return -1; }
-    let rdr = &mut *(handle as *mut FastCacheReader);
-    match rdr.next_msg() {
-        Ok(Some(p)) => { *out = p; (&*(p as *const MessageHeader)).size as i32 }
-        Ok(None)    => 0,
-        Err(_)      => -2,
+/// One compressed block as recorded by [`BlockIndex`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockIndexEntry {
+    /// Time of the block's first message (the previous block's for an empty one).
+    pub first_time: i64,
+    /// File offset of the block's length prefix.
+    pub file_pos: u64,
+    /// Compressed payload length, as in the prefix.
+    pub block_len: u32,
+}
+
+/// Per‑block offsets of a FastCache file, for random access by time,
+/// plus the file's [`FileMetadata`] tallied during the same scan.
+///
+/// On disk (all little‑endian): magic `FSIX`, `u32` version (2), `u64`
+/// length of the indexed file, `i64` its mtime in ns since the Unix epoch
+/// (`i64::MIN` if unknown), `u64` entry count, then per block
+/// `i64 first_time, u64 file_pos, u32 block_len`, then `u64 messages`,
+/// `i64 min_time, i64 max_time` (`i64::MAX, i64::MIN` when empty),
+/// `u32` kind count and per kind `i16 kind, u64 count`. Version 1 files,
+/// which end after the entries and record no mtime, still load.
+#[derive(Clone, Debug, Default)]
+pub struct BlockIndex {
+    file_len: u64,
+    mtime:    Option<i64>,
+    entries:  Vec<BlockIndexEntry>,
+    meta:     Option<FileMetadata>,
+}
+
+impl BlockIndex {
+    const MAGIC: &'static [u8; 4] = b"FSIX";
+    const VERSION: u32 = 2;
+    const ENTRY_LEN: usize = 20;
+
+    /// Scans `path` once, decompressing each block to find its first
+    /// message and tally its headers.
+    pub fn build(path: &str) -> Result<Self> {
+        // Taken before the scan, so a write during it makes the index stale.
+        let mtime = file_mtime(path)?;
+        let mut rdr = BlockReader::open(path)?;
+        let mut entries = Vec::new();
+        let mut meta = FileMetadata::default();
+        let mut time = i64::MIN;
+        while let Some(block) = rdr.next_block()? {
+            if let Some(h) = block.bytes.get(..size_of::<MessageHeader>()).map(|b| unsafe { MessageRef::from_framed(b) }.header()) {
+                if h.size != 0 { time = h.time; }
+            }
+            let block_len = block.compressed_len as u32;
+            entries.push(BlockIndexEntry { first_time: time, file_pos: block.file_pos, block_len });
+            meta.tally(block.bytes, block.file_pos)?;
+            if has_terminator(block.bytes) { break; }
+        }
+        Ok(Self { file_len: rdr.file_len.unwrap_or(rdr.file_pos), mtime, entries, meta: Some(meta) })
+    }
+
+    /// The sidecar of `path` if it still matches the file's length and
+    /// mtime, else a fresh [`BlockIndex::build`] saved over it. Saving is
+    /// best effort: on read‑only media the fresh index is still returned.
+    pub fn load_or_build(path: &str) -> Result<Self> {
+        let sidecar = Self::sidecar_path(path);
+        if let Ok(index) = Self::load(&sidecar) {
+            if index.is_current(path)? { return Ok(index); }
+        }
+        let index = Self::build(path)?;
+        let _ = index.save(&sidecar);
+        Ok(index)
+    }
+
+    /// Whether `path` still has the length and mtime this index was
+    /// built for. Always false for an index without an mtime.
+    pub fn is_current(&self, path: &str) -> Result<bool> {
+        let len = std::fs::metadata(path).with_context(|| format!("stat {path}"))?.len();
+        Ok(len == self.file_len && self.mtime.is_some() && self.mtime == file_mtime(path)?)
+    }
+
+    /// `<path>.idx`, where [`FastCacheReader::open_with_index`] looks.
+    pub fn sidecar_path(path: &str) -> String { format!("{path}.idx") }
+
+    pub fn entries(&self) -> &[BlockIndexEntry] { &self.entries }
+
+    /// Counts and time span of the whole file; `None` for an index
+    /// loaded from a version 1 sidecar.
+    pub fn metadata(&self) -> Option<&FileMetadata> { self.meta.as_ref() }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path).with_context(|| format!("create {path}"))?);
+        out.write_all(Self::MAGIC)?;
+        out.write_all(&Self::VERSION.to_le_bytes())?;
+        out.write_all(&self.file_len.to_le_bytes())?;
+        out.write_all(&self.mtime.unwrap_or(i64::MIN).to_le_bytes())?;
+        out.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for e in &self.entries {
+            out.write_all(&e.first_time.to_le_bytes())?;
+            out.write_all(&e.file_pos.to_le_bytes())?;
+            out.write_all(&e.block_len.to_le_bytes())?;
+        }
+        let meta = self.meta.clone().unwrap_or_default();
+        out.write_all(&meta.messages.to_le_bytes())?;
+        out.write_all(&meta.min_time.unwrap_or(i64::MAX).to_le_bytes())?;
+        out.write_all(&meta.max_time.unwrap_or(i64::MIN).to_le_bytes())?;
+        out.write_all(&(meta.kinds.len() as u32).to_le_bytes())?;
+        for (kind, n) in &meta.kinds {
+            out.write_all(&kind.to_le_bytes())?;
+            out.write_all(&n.to_le_bytes())?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("read {path}"))?;
+        ensure!(data.len() >= 24 && &data[..4] == Self::MAGIC, "{path} is not a block index");
+        let version = LittleEndian::read_u32(&data[4..]);
+        ensure!(version == 1 || version == Self::VERSION, "unsupported block index version {version}");
+        let file_len = LittleEndian::read_u64(&data[8..]);
+        let (mtime, head) = match version {
+            1 => (None, 16),
+            _ => {
+                ensure!(data.len() >= 32, "{path}: block index header truncated");
+                (Some(LittleEndian::read_i64(&data[16..])).filter(|&t| t != i64::MIN), 24)
+            }
+        };
+        let count = LittleEndian::read_u64(&data[head..]) as usize;
+        let body = &data[head + 8..];
+        let entries_len = count.checked_mul(Self::ENTRY_LEN).filter(|&n| n <= body.len()).ok_or_else(|| {
+            FastStorageError::Invalid(format!("block index holds {} bytes for {count} entries", body.len()))
+        })?;
+        let (body, tail) = body.split_at(entries_len);
+        let entries = body.chunks_exact(Self::ENTRY_LEN).map(|e| BlockIndexEntry {
+            first_time: LittleEndian::read_i64(e),
+            file_pos:   LittleEndian::read_u64(&e[8..]),
+            block_len:  LittleEndian::read_u32(&e[16..]),
+        }).collect::<Vec<_>>();
+        let meta = match version {
+            1 => {
+                ensure!(tail.is_empty(), "block index holds {} bytes for {count} entries", body.len() + tail.len());
+                None
+            }
+            _ => Some(Self::load_meta(tail, count as u64).with_context(|| format!("{path}: metadata"))?),
+        };
+        Ok(Self { file_len, mtime, entries, meta })
+    }
+
+    fn load_meta(tail: &[u8], blocks: u64) -> Result<FileMetadata> {
+        ensure!(tail.len() >= 28, "truncated");
+        let kinds = LittleEndian::read_u32(&tail[24..]) as usize;
+        ensure!(tail.len() - 28 == kinds * 10, "{} bytes for {kinds} kinds", tail.len() - 28);
+        let (min, max) = (LittleEndian::read_i64(&tail[8..]), LittleEndian::read_i64(&tail[16..]));
+        let messages = LittleEndian::read_u64(tail);
+        Ok(FileMetadata {
+            blocks, messages,
+            kinds: tail[28..].chunks_exact(10).map(|k| (LittleEndian::read_i16(k), LittleEndian::read_u64(&k[2..]))).collect(),
+            min_time: (messages > 0).then_some(min),
+            max_time: (messages > 0).then_some(max),
+        })
     }
 }
 
-#[no_mangle]
-pub extern "C" fn close_reader(h: *mut c_void) {
-    if !h.is_null() {
-        unsafe { drop(Box::from_raw(h as *mut FastCacheReader)) };
+/// Modification time of `path` in ns since the Unix epoch, if the
+/// platform records one.
+fn file_mtime(path: &str) -> Result<Option<i64>> {
+    let meta = std::fs::metadata(path).with_context(|| format!("stat {path}"))?;
+    Ok(meta.modified().ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|d| i64::try_from(d.as_nanos()).ok()))
+}
+
+/// Lending iterator over the messages of a [`FastCacheReader`]. Each
+/// [`MessageRef`] borrows the current block and is valid until the next
+/// call to [`MessageIter::next_message`].
+pub struct MessageIter<R = BufReader<File>> { rdr: FastCacheReader<R> }
+
+impl<R: Read> MessageIter<R> {
+    pub fn next_message(&mut self) -> Result<Option<MessageRef<'_>>> {
+        Ok(self.rdr.next_range()?.map(|r| unsafe { MessageRef::from_framed(&self.rdr.inner.src[r]) }))
+    }
+
+    /// [`next_message`](Self::next_message) together with the block it
+    /// came from; see [`BlockPosition`].
+    pub fn next_with_block(&mut self) -> Result<Option<(MessageRef<'_>, BlockPosition)>> {
+        let Some(r) = self.rdr.next_range()? else { return Ok(None) };
+        let at = BlockPosition { block_index: self.rdr.last_block, first_in_block: r.start == 0 };
+        Ok(Some((unsafe { MessageRef::from_framed(&self.rdr.inner.src[r]) }, at)))
+    }
+
+    pub fn get_ref(&self) -> &FastCacheReader<R> { &self.rdr }
+
+    pub fn into_inner(self) -> FastCacheReader<R> { self.rdr }
+}
+
+/// Which block a message came from. Writers often start a block with a
+/// `CLEAR` snapshot, so `first_in_block` marks a place to begin a book
+/// without parsing flags. It is set only for the message at the very
+/// start of the block: with kind or time filters, the first message
+/// returned from a block may not be it, so watch `block_index` change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockPosition {
+    /// Index of the block in the file, counting from 0.
+    pub block_index:    u64,
+    pub first_in_block: bool,
+}
+
+/// Lending iterator over the decompressed blocks of a [`FastCacheReader`],
+/// without parsing messages. Each [`BlockRef`] is valid until the next call
+/// to [`BlockIter::next_block`]. Lenient mode, checksum verification and
+/// `size == 0` terminators apply as for messages; a block holding a
+/// terminator is the last one yielded, in full.
+pub struct BlockIter<R = BufReader<File>> { rdr: FastCacheReader<R> }
+
+impl<R: Read> BlockIter<R> {
+    pub fn next_block(&mut self) -> Result<Option<BlockRef<'_>>> {
+        let rdr = &mut self.rdr;
+        if rdr.ended { return Ok(None); }
+        let Some(block) = rdr.inner.next_block()? else { return Ok(None) };
+        rdr.offset = block.bytes.len();
+        rdr.ended = has_terminator(block.bytes);
+        Ok(Some(block))
+    }
+
+    pub fn get_ref(&self) -> &FastCacheReader<R> { &self.rdr }
+
+    pub fn into_inner(self) -> FastCacheReader<R> { self.rdr }
+}
+
+/// One block from [`BlockIter`] or [`BlockReader`].
+#[derive(Clone, Copy, Debug)]
+pub struct BlockRef<'a> {
+    /// Position in the file, counting from 0 (skipped blocks included).
+    pub index:          u64,
+    /// File offset of the block's length prefix.
+    pub file_pos:       u64,
+    /// Pickled size on disk, excluding the length prefix and any CRC.
+    pub compressed_len: usize,
+    /// Exactly the decompressed bytes, `block_len` long.
+    pub bytes:          &'a [u8],
+}
+
+/// Unpacks `src_path` into `dst_path` as the raw concatenated message
+/// stream (no file header, no block framing). Returns the bytes written.
+pub fn decompress_to(src_path: &str, dst_path: &str) -> Result<u64> {
+    let mut rdr = BlockReader::open(src_path)?;
+    let mut out = BufWriter::new(File::create(dst_path).with_context(|| format!("create {dst_path}"))?);
+    let mut written = 0u64;
+    while let Some(block) = rdr.next_block()? {
+        out.write_all(block.bytes)?;
+        written += block.bytes.len() as u64;
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+/// Rewrites `src_path` into `dst_path` with blocks of up to
+/// `target_block_len` bytes, e.g. to repack a capture written with tiny
+/// blocks. Messages are copied byte for byte and in order; the output is
+/// checksummed if the input is. Returns the number of messages copied.
+pub fn transcode(src_path: &str, dst_path: &str, target_block_len: usize) -> Result<u64> {
+    let rdr = FastCacheReader::open(src_path)?;
+    let mut out = if rdr.has_checksums() {
+        FastCacheWriter::create_checksummed(dst_path, target_block_len, CompressionMode::Lz4)?
+    } else {
+        FastCacheWriter::create(dst_path, target_block_len, CompressionMode::Lz4)?
+    };
+    let mut msgs = rdr.messages();
+    let mut copied = 0u64;
+    while let Some(msg) = msgs.next_message()? {
+        out.write(msg.bytes())?;
+        copied += 1;
+    }
+    out.finish()?;
+    Ok(copied)
+}
+
+/// Splits a capture interleaving several instruments into one file per
+/// symbol, `<output_dir>/<name>.bin.lz4`. A `Symbol` message switches the
+/// instrument: it and every message up to the next one go to that
+/// symbol's file, in order. Messages before the first `Symbol` belong to
+/// no instrument and are dropped. In file names, anything but ASCII
+/// letters, digits, `-`, `_` and `.` becomes `_`; two symbols that end up
+/// with the same file name are an error. Outputs keep the input's buffer
+/// length and checksum setting. Returns the messages written per symbol,
+/// `Symbol` messages included.
+pub fn split_by_symbol(input: &str, output_dir: &str) -> Result<BTreeMap<String, u64>> {
+    let rdr = FastCacheReader::open(input)?;
+    let (buffer_len, checksums) = (rdr.buffer_len(), rdr.has_checksums());
+    std::fs::create_dir_all(output_dir).with_context(|| format!("create {output_dir}"))?;
+    let mut outputs: Vec<(String, FastCacheWriter, u64)> = Vec::new();
+    let mut by_file: BTreeMap<String, usize> = BTreeMap::new();
+    let mut current = None;
+    let mut msgs = rdr.messages();
+    while let Some(msg) = msgs.next_message()? {
+        if msg.message_kind() == Some(MessageKind::Symbol) {
+            let Message::Symbol(sym) = msg.decode()? else { unreachable!() };
+            let file: String = sym.name.chars()
+                .map(|c| if c.is_ascii_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+                .collect();
+            let file = if file.is_empty() { "_".to_string() } else { file };
+            let i = match by_file.get(&file) {
+                Some(&i) => {
+                    ensure!(outputs[i].0 == sym.name, "symbols {:?} and {:?} both map to {file}.bin.lz4", outputs[i].0, sym.name);
+                    i
+                }
+                None => {
+                    let path = format!("{output_dir}/{file}.bin.lz4");
+                    let out = if checksums {
+                        FastCacheWriter::create_checksummed(&path, buffer_len, CompressionMode::Lz4)?
+                    } else {
+                        FastCacheWriter::create(&path, buffer_len, CompressionMode::Lz4)?
+                    };
+                    outputs.push((sym.name, out, 0));
+                    by_file.insert(file, outputs.len() - 1);
+                    outputs.len() - 1
+                }
+            };
+            current = Some(i);
+        }
+        if let Some(i) = current {
+            let (_, out, written) = &mut outputs[i];
+            out.write(msg.bytes())?;
+            *written += 1;
+        }
+    }
+    let mut counts = BTreeMap::new();
+    for (name, out, written) in outputs {
+        out.finish()?;
+        counts.insert(name, written);
+    }
+    Ok(counts)
+}
+
+/// Message counts and time span of a file, from [`scan_metadata`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    pub blocks:   u64,
+    pub messages: u64,
+    /// Messages per raw `header.kind`, unknown kinds included.
+    pub kinds:    BTreeMap<i16, u64>,
+    /// Smallest and largest `header.time`; `None` for an empty file.
+    pub min_time: Option<i64>,
+    pub max_time: Option<i64>,
+}
+
+impl FileMetadata {
+    pub fn count(&self, kind: MessageKind) -> u64 {
+        self.kinds.get(&(kind as i16)).copied().unwrap_or(0)
+    }
+
+    /// Adds the headers of one decompressed block.
+    fn tally(&mut self, block: &[u8], file_pos: u64) -> Result<()> {
+        self.blocks += 1;
+        let mut at = 0;
+        while block.len() - at >= size_of::<MessageHeader>() {
+            let Some(r) = message_range(block, at).with_context(|| format!("block at byte {file_pos}"))? else { break };
+            let MessageHeader { kind, time, .. } = unsafe { MessageRef::from_framed(&block[r.clone()]) }.header();
+            *self.kinds.entry(kind).or_default() += 1;
+            self.messages += 1;
+            self.min_time = Some(self.min_time.map_or(time, |t| t.min(time)));
+            self.max_time = Some(self.max_time.map_or(time, |t| t.max(time)));
+            at = r.end;
+        }
+        Ok(())
+    }
+}
+
+/// Tallies `path` by reading only message headers: every block is still
+/// decompressed, but nothing is decoded or copied. When a [`BlockIndex`]
+/// sidecar matching the file exists (see [`BlockIndex::is_current`]) its
+/// recorded totals are returned instead, without reading the file.
+pub fn scan_metadata(path: &str) -> Result<FileMetadata> {
+    if let Ok(index) = BlockIndex::load(&BlockIndex::sidecar_path(path)) {
+        if let Some(meta) = index.meta.as_ref().filter(|_| index.is_current(path).unwrap_or(false)) {
+            return Ok(meta.clone());
+        }
+    }
+    let mut blocks = FastCacheReader::open(path)?.blocks();
+    let mut meta = FileMetadata::default();
+    while let Some(block) = blocks.next_block()? { meta.tally(block.bytes, block.file_pos)?; }
+    Ok(meta)
+}
+
+/// Every trade of a file as parallel columns in raw wire units, ready for
+/// Arrow, Polars or SIMD loops. Costs 33 bytes per trade (about 66 MB for
+/// 2 M trades). On large files, size the columns from a [`scan_metadata`]
+/// count and fill them with [`TickColumns::extend_from_reader`] to avoid
+/// regrowing:
+///
+/// ```text
+/// let meta = scan_metadata("file.bin.lz4")?;
+/// let mut ticks = TickColumns::with_capacity(meta.count(MessageKind::Tick) as usize);
+/// ticks.extend_from_reader(FastCacheReader::open("file.bin.lz4")?)?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TickColumns {
+    pub time:   Vec<i64>,
+    pub id:     Vec<i64>,
+    pub price:  Vec<i64>,
+    pub volume: Vec<i64>,
+    pub side:   Vec<u8>,
+}
+
+impl TickColumns {
+    pub fn with_capacity(n: usize) -> Self {
+        let col = || Vec::with_capacity(n);
+        Self { time: col(), id: col(), price: col(), volume: col(), side: Vec::with_capacity(n) }
+    }
+
+    pub fn push(&mut self, t: &TickItem) {
+        self.time.push(t.header.time);
+        self.id.push(t.id());
+        self.price.push(t.price());
+        self.volume.push(t.volume());
+        self.side.push(t.raw_side());
+    }
+
+    /// Appends every trade left in `reader`, in file order. Other kinds are
+    /// skipped, and the reader's kind filter is replaced.
+    pub fn extend_from_reader<R: Read>(&mut self, mut reader: FastCacheReader<R>) -> Result<()> {
+        reader.set_kind_filter(&[MessageKind::Tick]);
+        let mut msgs = reader.messages();
+        while let Some(msg) = msgs.next_message()? {
+            if let Some(t) = msg.as_tick() { self.push(&t); }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize { self.time.len() }
+    pub fn is_empty(&self) -> bool { self.time.is_empty() }
+}
+
+/// Depth updates as columns, like [`TickColumns`]; 25 bytes each.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DepthColumns {
+    pub time:   Vec<i64>,
+    pub price:  Vec<i64>,
+    pub volume: Vec<i64>,
+    pub flags:  Vec<u8>,
+}
+
+impl DepthColumns {
+    pub fn with_capacity(n: usize) -> Self {
+        let col = || Vec::with_capacity(n);
+        Self { time: col(), price: col(), volume: col(), flags: Vec::with_capacity(n) }
+    }
+
+    pub fn push(&mut self, d: &DepthItem) {
+        self.time.push(d.header.time);
+        self.price.push(d.price());
+        self.volume.push(d.volume());
+        self.flags.push(d.flags());
+    }
+
+    /// [`TickColumns::extend_from_reader`] for depth updates.
+    pub fn extend_from_reader<R: Read>(&mut self, mut reader: FastCacheReader<R>) -> Result<()> {
+        reader.set_kind_filter(&[MessageKind::Depth]);
+        let mut msgs = reader.messages();
+        while let Some(msg) = msgs.next_message()? {
+            if let Some(d) = msg.as_depth() { self.push(&d); }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize { self.time.len() }
+    pub fn is_empty(&self) -> bool { self.time.is_empty() }
+}
+
+/// Every trade left in `reader`, as columns; see [`TickColumns`].
+pub fn read_all_ticks_columnar<R: Read>(reader: FastCacheReader<R>) -> Result<TickColumns> {
+    let mut cols = TickColumns::default();
+    cols.extend_from_reader(reader)?;
+    Ok(cols)
+}
+
+/// Every depth update left in `reader`, as columns.
+pub fn read_all_depth_columnar<R: Read>(reader: FastCacheReader<R>) -> Result<DepthColumns> {
+    let mut cols = DepthColumns::default();
+    cols.extend_from_reader(reader)?;
+    Ok(cols)
+}
+
+/// 64‑bit FNV‑1a over the bytes of every message (headers included), in
+/// stream order. Only the messages count: two files hashing alike hold
+/// the same messages even if block sizes, compression, checksums or byte
+/// order differ, as after [`transcode`]. The value is part of the format's
+/// contract and won't change between versions, so it can be pinned in
+/// regression checks. Kind and time filters set on `reader` apply.
+pub fn stream_digest<R: Read>(reader: FastCacheReader<R>) -> Result<u64> {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME:  u64 = 0x0000_0100_0000_01b3;
+    let mut msgs = reader.messages();
+    let mut hash = OFFSET;
+    while let Some(msg) = msgs.next_message()? {
+        for &b in msg.bytes() { hash = (hash ^ b as u64).wrapping_mul(PRIME); }
+    }
+    Ok(hash)
+}
+
+/// Writes one JSON object per message, for eyeballing a capture:
+///
+/// ```text
+/// {"seq":0,"block_pos":4,"offset":0,"kind":"Depth","time":1,"price":10000000000,"volume":5,"flags":1}
+/// ```
+///
+/// `seq` counts messages from 0. `block_pos` is the file offset of the
+/// block's length prefix and `offset` the message's offset inside the
+/// decompressed block, as in [`FastCacheReader::current_message_location`]. Prices and volumes
+/// are the raw fixed‑point ×1e8 integers. Messages that fail to decode
+/// (unknown kinds, short structs) become
+/// `"kind":"Invalid"` lines carrying the raw `kind`, `size`, and
+/// `error`, and the dump goes on. Returns the number of lines written.
+pub fn dump_jsonl<R: Read, W: Write>(reader: FastCacheReader<R>, mut out: W) -> Result<u64> {
+    let mut msgs = reader.messages();
+    let mut seq = 0u64;
+    while let Some(msg) = msgs.next_message()? {
+        let (kind, size, decoded) = (msg.kind(), msg.size(), msg.decode());
+        let (block_pos, offset) = msgs.get_ref().current_message_location();
+        write!(out, r#"{{"seq":{seq},"block_pos":{block_pos},"offset":{offset},"#)?;
+        match decoded {
+            Ok(Message::Depth(d)) => {
+                let (header, price, volume, flags) = (d.header, d.price(), d.volume(), d.flags());
+                write!(out, r#""kind":"Depth","time":{},"price":{price},"volume":{volume},"flags":{flags}"#, { header.time })?;
+            }
+            Ok(Message::Tick(t)) => {
+                let (header, id, price, volume, side) = (t.header, t.id(), t.price(), t.volume(), t.raw_side());
+                write!(out, r#""kind":"Tick","time":{},"id":{id},"price":{price},"volume":{volume},"side":{side}"#, { header.time })?;
+            }
+            Ok(Message::Symbol(s)) => {
+                write!(out, r#""kind":"Symbol","time":{},"name":"#, { s.header.time })?;
+                write_json_str(&mut out, &s.name)?;
+            }
+            Ok(Message::Candle(c)) => {
+                let (header, open, high, low, close, volume) = (c.header, c.open(), c.high(), c.low(), c.close(), c.volume());
+                write!(
+                    out,
+                    r#""kind":"Candle","time":{},"open":{open},"high":{high},"low":{low},"close":{close},"volume":{volume}"#,
+                    { header.time }
+                )?;
+            }
+            Ok(Message::CandleEnd(h)) => write!(out, r#""kind":"CandleEnd","time":{}"#, { h.time })?,
+            Err(e) => {
+                write!(out, r#""kind":"Invalid","raw_kind":{kind},"size":{size},"error":"#)?;
+                write_json_str(&mut out, &e.to_string())?;
+            }
+        }
+        writeln!(out, "}}")?;
+        seq += 1;
+    }
+    out.flush()?;
+    Ok(seq)
+}
+
+fn write_json_str<W: Write>(out: &mut W, s: &str) -> std::io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"'  => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c    => write!(out, "{c}")?,
+        }
+    }
+    out.write_all(b"\"")
+}
+
+/// Chains files (e.g. hourly rotations of one capture) into a single
+/// message stream, opening each as the previous one runs out. All files
+/// must declare the same buffer length; this is checked up front so a
+/// missing or foreign file fails before replay starts. A `size == 0`
+/// terminator ends its own file; the chain moves on to the next one.
+pub struct MultiFileReader {
+    paths:    Vec<String>,
+    next:     usize,
+    cur:      Option<FastCacheReader>,
+    last:     Option<i64>,     // time of the last message handed out
+    fresh:    bool,            // nothing read from `cur` yet
+    warnings: Vec<String>,
+}
+
+impl MultiFileReader {
+    pub fn open<P: AsRef<str>>(paths: &[P]) -> Result<Self> {
+        let paths: Vec<String> = paths.iter().map(|p| p.as_ref().to_owned()).collect();
+        let mut buf_len = None;
+        for path in &paths {
+            let len = FastCacheReader::open(path).with_context(|| format!("reading header of {path}"))?.buffer_len();
+            let first = *buf_len.get_or_insert(len);
+            ensure!(len == first, "{path}: buffer length {len} differs from {first} in {}", paths[0]);
+        }
+        Ok(Self { paths, next: 0, cur: None, last: None, fresh: false, warnings: Vec::new() })
+    }
+
+    /// Path of the file the last message came from.
+    pub fn current_path(&self) -> Option<&str> {
+        self.next.checked_sub(1).map(|i| self.paths[i].as_str())
+    }
+
+    /// Files found to start earlier than the previous one ended.
+    pub fn warnings(&self) -> &[String] { &self.warnings }
+
+    pub fn next_message(&mut self) -> Result<Option<MessageRef<'_>>> {
+        let Some(r) = self.next_range()? else { return Ok(None) };
+        let rdr = self.cur.as_ref().expect("set by next_range");
+        Ok(Some(unsafe { MessageRef::from_framed(&rdr.inner.src[r]) }))
+    }
+
+    pub fn next_typed(&mut self) -> Result<Option<Message>> {
+        Ok(match self.next_message()? { Some(m) => Some(m.decode()?), None => None })
+    }
+
+    fn next_range(&mut self) -> Result<Option<Range<usize>>> {
+        loop {
+            if let Some(rdr) = &mut self.cur {
+                let path = &self.paths[self.next - 1];
+                if let Some(r) = rdr.next_range().with_context(|| format!("in {path}"))? {
+                    let time = rdr.header_at(r.start).time;
+                    if let Some(prev) = self.last.filter(|&prev| self.fresh && time < prev) {
+                        self.warnings.push(format!("{path} starts at {time}, before the previous file ended at {prev}"));
+                    }
+                    self.fresh = false;
+                    self.last = Some(time);
+                    return Ok(Some(r));
+                }
+            }
+            let Some(path) = self.paths.get(self.next) else { return Ok(None) };
+            self.cur = Some(FastCacheReader::open(path)?);
+            self.next += 1;
+            self.fresh = true;
+        }
+    }
+}
+
+/// Replays a file at the pace it was recorded, for driving dashboards and
+/// demos rather than backtests: each message is held back until its
+/// `header.time`, read as .NET ticks (see [`MessageHeader::to_unix_nanos`]),
+/// is due relative to the first one, scaled by `speed` (2.0 plays twice
+/// as fast). Due times are measured from the first message rather than
+/// from the previous one, so sleep overshoot doesn't add up over a long
+/// replay, and block boundaries make no difference. A message already
+/// due, such as one whose time went backwards, is handed out at once.
+pub struct PacedReader<R = BufReader<File>> {
+    rdr:   FastCacheReader<R>,
+    speed: f64,
+    start: Option<(Instant, i64)>,  // wall clock and message time of the first message
+}
+
+impl<R: Read> PacedReader<R> {
+    /// `speed` must be positive and finite.
+    pub fn new(rdr: FastCacheReader<R>, speed: f64) -> Result<Self> {
+        ensure!(speed > 0.0 && speed.is_finite(), "replay speed must be positive and finite, got {speed}");
+        Ok(Self { rdr, speed, start: None })
+    }
+
+    /// The next message, once it is due; see [`MessageIter::next_message`].
+    pub fn next_message(&mut self) -> Result<Option<MessageRef<'_>>> {
+        let Some(r) = self.rdr.next_range()? else { return Ok(None) };
+        self.wait(self.rdr.header_at(r.start));
+        Ok(Some(unsafe { MessageRef::from_framed(&self.rdr.inner.src[r]) }))
+    }
+
+    pub fn next_typed(&mut self) -> Result<Option<Message>> {
+        Ok(match self.next_message()? { Some(m) => Some(m.decode()?), None => None })
+    }
+
+    pub fn get_ref(&self) -> &FastCacheReader<R> { &self.rdr }
+
+    pub fn into_inner(self) -> FastCacheReader<R> { self.rdr }
+
+    /// Sleeps until `h` is due. Times outside what `to_unix_nanos` covers
+    /// are not paced.
+    fn wait(&mut self, h: MessageHeader) {
+        let Some(nanos) = h.to_unix_nanos() else { return };
+        let &mut (wall, first) = self.start.get_or_insert((Instant::now(), nanos));
+        let ahead = nanos.saturating_sub(first) as f64 / self.speed;
+        if ahead <= 0.0 { return; }
+        let due = wall + Duration::from_nanos(ahead.min(u64::MAX as f64) as u64);
+        if let Some(d) = due.checked_duration_since(Instant::now()) { thread::sleep(d); }
+    }
+}
+
+/// Decode settings of the reader a [`Job`] came from.
+#[derive(Clone, Copy)]
+struct Decode { max_block: usize, crc_len: usize, endian: Endian }
+
+type BlockReply = mpsc::Sender<(u64, Result<DecodedBlock>)>;
+
+/// One compressed block on its way to a worker, with where to send it back.
+struct Job { seq: u64, file_pos: u64, cmp: Vec<u8>, decode: Decode, reply: BlockReply }
+
+impl Job {
+    fn run(self) {
+        let Job { seq, file_pos, cmp, decode: Decode { max_block, crc_len, endian }, reply } = self;
+        let (cmp_buf, crc) = cmp.split_at(cmp.len() - crc_len);
+        let block = unpickle_block(cmp_buf, max_block)
+            .and_then(|mut data| {
+                if crc_len != 0 { verify_block(seq, crc, &data, endian)?; }
+                if endian == Endian::Big { swap_block(&mut data); }
+                Ok(DecodedBlock::new(file_pos, data))
+            })
+            .with_context(|| format!("block at byte {file_pos}"));
+        // A consumer dropped mid‑stream leaves its last jobs unclaimed.
+        let _ = reply.send((seq, block));
+    }
+}
+
+/// Workers that run jobs from `job_rx` until every sender is gone.
+fn spawn_workers(threads: usize, job_rx: mpsc::Receiver<Job>) -> Vec<thread::JoinHandle<()>> {
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    (0..threads).map(|_| {
+        let job_rx = Arc::clone(&job_rx);
+        thread::spawn(move || loop {
+            let job = match job_rx.lock() {
+                Ok(rx) => match rx.recv() { Ok(job) => job, Err(_) => return },
+                Err(_) => return,
+            };
+            job.run();
+        })
+    }).collect()
+}
+
+/// Where [`ParBlocks`] sends its jobs: its own workers, or a [`ReaderPool`].
+enum JobQueue { Own(mpsc::SyncSender<Job>), Shared(mpsc::Sender<Job>) }
+
+impl JobQueue {
+    fn send(&self, job: Job) -> bool {
+        match self {
+            JobQueue::Own(tx)    => tx.send(job).is_ok(),
+            JobQueue::Shared(tx) => tx.send(job).is_ok(),
+        }
+    }
+}
+
+/// Iterator over decompressed blocks in file order, with decompression
+/// running on a pool of worker threads. Reading stays on the calling
+/// thread; at most `read_ahead` blocks are in flight or waiting to be
+/// consumed. Dropping it stops and joins the workers it started.
+pub struct ParBlocks<R = BufReader<File>> {
+    rdr:     BlockReader<R>,
+    jobs:    Option<JobQueue>,
+    decode:  Decode,
+    reply:   BlockReply,
+    results: mpsc::Receiver<(u64, Result<DecodedBlock>)>,
+    workers: Vec<thread::JoinHandle<()>>,
+    ready:   BTreeMap<u64, Result<DecodedBlock>>,
+    sent:    u64,
+    yielded: u64,
+    ahead:   u64,
+    eof:     bool,
+}
+
+impl<R: Read> FastCacheReader<R> {
+    /// Consumes the reader into a parallel block iterator with `threads`
+    /// decompression workers and up to `read_ahead` blocks buffered.
+    /// Starts at the next unread block; the rest of a partially consumed
+    /// block is dropped. Decode and checksum errors are yielded in place
+    /// of their block; a framing error ends the stream after it is yielded,
+    /// and so does a block holding a `size == 0` terminator.
+    pub fn par_blocks(self, threads: usize, read_ahead: usize) -> ParBlocks<R> {
+        let ahead = read_ahead.max(1);
+        let (jobs, job_rx) = mpsc::sync_channel::<Job>(ahead);
+        let workers = spawn_workers(threads.max(1), job_rx);
+        ParBlocks::new(self, JobQueue::Own(jobs), workers, ahead)
+    }
+}
+
+/// A fixed set of decompression threads shared by any number of readers,
+/// so replaying dozens of files at once runs `threads` decoders in total
+/// rather than a pool per file.
+///
+/// [`ReaderPool::par_blocks`] hands out the same [`ParBlocks`] iterator as
+/// [`FastCacheReader::par_blocks`]. Jobs from all readers share one queue
+/// and are decoded in the order they were read. Backpressure is per
+/// reader: each one reads ahead at most `read_ahead` blocks beyond what
+/// its consumer has taken, so a consumer that falls behind stops its own
+/// file being read and holds at most that many blocks in memory, while
+/// the workers carry on with the other readers' blocks. A busy pool
+/// delays every reader alike; none is starved, as the queue is FIFO.
+///
+/// Workers exit once the pool and every iterator it handed out are
+/// dropped. Blocks a dropped iterator had already queued are still
+/// decoded and then discarded.
+pub struct ReaderPool { jobs: mpsc::Sender<Job>, threads: usize }
+
+impl ReaderPool {
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (jobs, job_rx) = mpsc::channel();
+        drop(spawn_workers(threads, job_rx));
+        Self { jobs, threads }
+    }
+
+    pub fn threads(&self) -> usize { self.threads }
+
+    /// [`FastCacheReader::par_blocks`] on this pool's workers.
+    pub fn par_blocks<R: Read>(&self, reader: FastCacheReader<R>, read_ahead: usize) -> ParBlocks<R> {
+        ParBlocks::new(reader, JobQueue::Shared(self.jobs.clone()), Vec::new(), read_ahead.max(1))
+    }
+}
+
+impl<R: Read> ParBlocks<R> {
+    fn new(reader: FastCacheReader<R>, jobs: JobQueue, workers: Vec<thread::JoinHandle<()>>, ahead: usize) -> Self {
+        let (rdr, ended) = (reader.inner, reader.ended);
+        let crc_len = if rdr.checksums && rdr.verify { 4 } else { 0 };
+        let decode = Decode { max_block: rdr.max_block, crc_len, endian: rdr.endian };
+        let (reply, results) = mpsc::channel();
+        let first = rdr.blocks;
+        let mut ready = BTreeMap::new();
+        let bad = match rdr.check_buffer_len() {
+            Ok(())  => false,
+            Err(e)  => { ready.insert(first, Err(e)); true }
+        };
+        ParBlocks {
+            rdr, jobs: Some(jobs), decode, reply, results, workers, ready,
+            sent: first + bad as u64, yielded: first, ahead: ahead as u64, eof: ended || bad,
+        }
+    }
+
+    /// Reads frames until `read_ahead` blocks are outstanding.
+    fn fill(&mut self) {
+        let rdr = &mut self.rdr;
+        let trailer = if rdr.checksums { 4 } else { 0 };
+        let strip = if rdr.verify { 0 } else { trailer };
+        while !self.eof && self.sent - self.yielded < self.ahead {
+            let file_pos = rdr.file_pos;
+            let fr = rdr.framing();
+            match rdr.input.read_frame(&mut rdr.file_pos, &mut rdr.cmp, fr) {
+                Ok(Some(buf)) => {
+                    let cmp = buf[..buf.len() - strip].to_vec();
+                    let job = Job { seq: self.sent, file_pos, cmp, decode: self.decode, reply: self.reply.clone() };
+                    if let Some(jobs) = &self.jobs { if !jobs.send(job) { self.eof = true; } }
+                }
+                Ok(None) => { self.eof = true; return; }
+                Err(e)   => {
+                    self.ready.insert(self.sent, Err(e.context(format!("block at byte {file_pos}"))));
+                    self.eof = true;
+                }
+            }
+            self.sent += 1;
+        }
+    }
+}
+
+impl<R: Read> Iterator for ParBlocks<R> {
+    type Item = Result<DecodedBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fill();
+        if self.yielded == self.sent { return None; }
+        loop {
+            if let Some(block) = self.ready.remove(&self.yielded) {
+                self.yielded += 1;
+                if block.as_ref().is_ok_and(|b| b.is_last()) {
+                    // Stream terminator: drop whatever was read past it.
+                    self.eof     = true;
+                    self.sent    = self.yielded;
+                    self.ready.clear();
+                }
+                return Some(block);
+            }
+            match self.results.recv() {
+                Ok((seq, block)) => { self.ready.insert(seq, block); }
+                Err(_) => {
+                    self.eof = true;
+                    self.yielded = self.sent;
+                    return Some(Err(std::io::Error::other("decompression workers exited").into()));
+                }
+            }
+        }
+    }
+}
+
+impl<R> Drop for ParBlocks<R> {
+    fn drop(&mut self) {
+        self.jobs = None;   // closes the queue; idle workers see it and exit
+        for w in self.workers.drain(..) { let _ = w.join(); }
+    }
+}
+
+/* ────────────────  2. writer implementation  ───────────────────────── */
+
+/// Produces files [`FastCacheReader`] can read: the 4‑byte buffer length,
+/// then length‑prefixed pickled blocks of at most `buffer_len` bytes.
+/// Output is deterministic — the same messages and buffer length always
+/// give byte‑identical files.
+pub struct FastCacheWriter {
+    file:      BufWriter<File>,
+    staging:   Vec<u8>,
+    buf_len:   usize,
+    mode:      CompressionMode,
+    checksums: bool,
+}
+
+impl FastCacheWriter {
+    pub fn create(path: &str, buffer_len: usize, mode: CompressionMode) -> Result<Self> {
+        Self::create_inner(path, buffer_len, mode, false)
+    }
+
+    /// Like [`FastCacheWriter::create`], but follows every block with a
+    /// CRC‑32 of its decompressed bytes and flags this in the file header.
+    /// Only readers with checksum support can open the result.
+    pub fn create_checksummed(path: &str, buffer_len: usize, mode: CompressionMode) -> Result<Self> {
+        Self::create_inner(path, buffer_len, mode, true)
+    }
+
+    fn create_inner(path: &str, buffer_len: usize, mode: CompressionMode, checksums: bool) -> Result<Self> {
+        ensure!(buffer_len > 0 && buffer_len < i32::MAX as usize, "invalid buffer length {buffer_len}");
+        let mut file = BufWriter::new(File::create(path).with_context(|| format!("create {path}"))?);
+        let flag = if checksums { CHECKSUM_FLAG } else { 0 };
+        file.write_all(&(buffer_len as i32 | flag).to_le_bytes())?;
+        Ok(Self { file, staging: Vec::with_capacity(buffer_len), buf_len: buffer_len, mode, checksums })
+    }
+
+    /// Continues an existing file, e.g. after a capture restart: new blocks
+    /// go after the last complete one, with the buffer length and checksum
+    /// setting taken from the file header, which is left untouched. A final
+    /// block cut short by a crash (its length prefix running past the end
+    /// of the file) is dropped first, so the file stays readable. A bad
+    /// prefix anywhere else is a [`FastStorageError::CorruptBlock`] and the
+    /// file is not modified. A missing or empty file is created as by
+    /// [`FastCacheWriter::create`]; `buffer_len` is only used then.
+    pub fn append(path: &str, buffer_len: usize, mode: CompressionMode) -> Result<Self> {
+        let mut f = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+            .with_context(|| format!("open {path}"))?;
+        let len = f.metadata().with_context(|| format!("stat {path}"))?.len();
+        if len == 0 { return Self::create_inner(path, buffer_len, mode, false); }
+
+        let mut hdr = [0u8; 4];
+        if read_full(&mut f, &mut hdr)? < 4 { return Err(FastStorageError::HeaderTruncated); }
+        let raw = LittleEndian::read_i32(&hdr);
+        if raw == ZSTD_MAGIC {
+            return Err(FastStorageError::CorruptBlock(format!("{path} is zstd‑compressed; decompress it before appending")));
+        }
+        let checksums = raw & CHECKSUM_FLAG != 0;
+        let buf_len = (raw & !CHECKSUM_FLAG) as usize;
+        if buf_len == 0 { return Err(FastStorageError::CorruptBlock(format!("invalid buffer length in {path}"))); }
+        if buf_len > DEFAULT_MAX_BLOCK_LEN {
+            return Err(FastStorageError::BlockTooLarge { len: buf_len, limit: DEFAULT_MAX_BLOCK_LEN }
+                .context(format!("{path}: file header declares a buffer above the limit")));
+        }
+
+        // Walk the length prefixes to the end of the last whole block.
+        let trailer = if checksums { 4 } else { 0 };
+        let max_cmp = k4os_pickler::max_pickled_len(DEFAULT_MAX_BLOCK_LEN) as u64;
+        let mut end = 4u64;
+        while len - end >= 4 {
+            f.seek(SeekFrom::Start(end))?;
+            f.read_exact(&mut hdr)?;
+            let cmp_len = LittleEndian::read_i32(&hdr);
+            if cmp_len <= 0 || cmp_len as u64 > max_cmp {
+                return Err(FastStorageError::CorruptBlock(format!("{path}: invalid compressed length {cmp_len} at byte {end}")));
+            }
+            match (end + 4).checked_add(cmp_len as u64 + trailer) {
+                Some(next) if next <= len => end = next,
+                _                         => break,   // the torn final frame
+            }
+        }
+        if end < len { f.set_len(end)?; }
+        f.seek(SeekFrom::Start(end))?;
+        Ok(Self { file: BufWriter::new(f), staging: Vec::with_capacity(buf_len), buf_len, mode, checksums })
+    }
+
+    /// Appends one encoded message (header included). The staged block is
+    /// written out first if the message would not fit in it.
+    pub fn write(&mut self, msg: &[u8]) -> Result<()> {
+        ensure!(
+            msg.len() >= size_of::<MessageHeader>() && LittleEndian::read_u16(&msg[2..4]) as usize == msg.len(),
+            "message of {} bytes does not match its header size", msg.len()
+        );
+        ensure!(msg.len() <= self.buf_len, "message of {} bytes exceeds buffer length {}", msg.len(), self.buf_len);
+        if self.staging.len() + msg.len() > self.buf_len {
+            self.flush_block()?;
+        }
+        self.staging.extend_from_slice(msg);
+        Ok(())
+    }
+
+    pub fn write_typed(&mut self, msg: &Message) -> Result<()> {
+        let mut buf = Vec::new();
+        msg.encode_into(&mut buf);
+        self.write(&buf)
+    }
+
+    /// Writes out the staged messages as a block, however small, and
+    /// flushes the file, so a reader tailing it sees everything written so
+    /// far. Flushing often gives small blocks that compress poorly; on a
+    /// timer of a second or so the cost is negligible.
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_block()?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Writes the last partial block and flushes the file.
+    pub fn finish(mut self) -> Result<()> {
+        self.flush_block()?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        if self.staging.is_empty() { return Ok(()); }
+        let block = k4os_pickler::pickle_with(&self.staging, self.mode)?;
+        self.file.write_all(&(block.len() as i32).to_le_bytes())?;
+        self.file.write_all(&block)?;
+        if self.checksums {
+            self.file.write_all(&crc32::checksum(&self.staging).to_le_bytes())?;
+        }
+        self.staging.clear();
+        Ok(())
+    }
+}
+
+impl Drop for FastCacheWriter {
+    fn drop(&mut self) { let _ = self.flush_block(); }
+}
+
+/* ────────────────  3. C‑ABI exports  ───────────────────────────────── */
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(i32, String)>> = const { RefCell::new(None) };
+}
+
+/// A C path as `&str`. Paths that are not UTF‑8 are refused rather than
+/// converted lossily, which would open or create a different file.
+unsafe fn c_path<'a>(path: *const c_char) -> Result<&'a str> {
+    CStr::from_ptr(path).to_str().map_err(|e| FastStorageError::Invalid(format!("path is not valid UTF-8 (invalid byte at {})", e.valid_up_to())))
+}
+
+/// Records `e` for `get_last_error` and returns its code.
+fn set_last_error(e: FastStorageError) -> i32 {
+    let code = e.code();
+    LAST_ERROR.with(|l| *l.borrow_mut() = Some((code, e.to_string())));
+    code
+}
+
+/// Copies the calling thread's most recent error into `buf` as a
+/// NUL‑terminated string, truncated to fit, like `snprintf`. Returns the
+/// full message length (without the NUL), so a result `>= len` means the
+/// buffer was too small. Returns 0 if no error has occurred.
+///
+/// # Safety
+/// `buf` must be writable for `len` bytes (or null with `len == 0`).
+#[no_mangle]
+pub unsafe extern "C" fn get_last_error(buf: *mut c_char, len: usize) -> i32 {
+    LAST_ERROR.with(|l| {
+        let l = l.borrow();
+        let Some((_, msg)) = l.as_ref() else { return 0 };
+        if !buf.is_null() && len > 0 {
+            let n = msg.len().min(len - 1);
+            ptr::copy_nonoverlapping(msg.as_ptr(), buf as *mut u8, n);
+            *buf.add(n) = 0;
+        }
+        msg.len() as i32
+    })
+}
+
+/// `ERR_*` code of the calling thread's most recent error, 0 if none.
+#[no_mangle]
+pub extern "C" fn get_last_error_code() -> i32 {
+    LAST_ERROR.with(|l| l.borrow().as_ref().map_or(0, |&(code, _)| code))
+}
+
+/// The handle may be passed to another thread, but calls on one handle
+/// must not overlap.
+///
+/// # Safety
+/// `path` must be a NUL‑terminated string and `out` a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn open_reader(path: *const c_char, out: *mut *mut c_void) -> i32 {
+    if path.is_null() || out.is_null() { return -1; }
+    let path = match c_path(path) {
+        Ok(p)  => p,
+        Err(e) => return set_last_error(e),
+    };
+    match FastCacheReader::open(path) {
+        Ok(r)  => { *out = Box::into_raw(Box::new(r)) as *mut _; 0 }
+        Err(e) => set_last_error(e),
+    }
+}
+
+/// `open_reader` on a file descriptor the caller already holds, such as a
+/// memfd, an unlinked file or a pipe. The reader takes ownership of `fd`:
+/// `close_reader` closes it, and so does a failed open. To keep using the
+/// descriptor, pass a `dup` of it. Reading starts at the descriptor's
+/// current offset, which should be the start of the file.
+///
+/// # Safety
+/// `fd` must be an open, readable descriptor not owned by anything else,
+/// and `out` a writable pointer.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn open_reader_fd(fd: i32, out: *mut *mut c_void) -> i32 {
+    use std::os::unix::io::FromRawFd;
+    if fd < 0 || out.is_null() { return -1; }
+    match FastCacheReader::from_file(File::from_raw_fd(fd)) {
+        Ok(r)  => { *out = Box::into_raw(Box::new(r)) as *mut _; 0 }
+        Err(e) => set_last_error(e),
+    }
+}
+
+/// `open_reader` with block buffers pre‑sized to `initial_capacity` bytes
+/// (0 keeps the file header's buffer length). Only a performance hint.
+///
+/// # Safety
+/// `path` must be a NUL‑terminated string and `out` a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn open_reader_ex(path: *const c_char, initial_capacity: usize, out: *mut *mut c_void) -> i32 {
+    if path.is_null() || out.is_null() { return -1; }
+    let path = match c_path(path) {
+        Ok(p)  => p,
+        Err(e) => return set_last_error(e),
+    };
+    match FastCacheReader::open_with_capacity(path, initial_capacity) {
+        Ok(r)  => { *out = Box::into_raw(Box::new(r)) as *mut _; 0 }
+        Err(e) => set_last_error(e),
+    }
+}
+
+/// Stores the next message's address in `*out` and returns its size; 0
+/// at end of stream, an `ERR_*` code on error.
+///
+/// # Safety
+/// `handle` must come from `open_reader`; the returned pointer is valid
+/// until the next call on the same handle.
+#[no_mangle]
+pub unsafe extern "C" fn read_message(handle: *mut c_void, out: *mut *const c_void) -> i32 {
+    if handle.is_null() || out.is_null() { return -1; }
+    let rdr = &mut *(handle as *mut FastCacheReader);
+    match rdr.next_msg() {
+        Ok(Some(p)) => { *out = p; (&*(p as *const MessageHeader)).size as i32 }
+        Ok(None)    => 0,
+        Err(e)      => set_last_error(e),
+    }
+}
+
+/// Fills `out_ptrs`/`out_sizes` with up to `max` messages and returns how
+/// many were written: 0 at end of stream, an `ERR_*` code on error. A batch never spans
+/// blocks, so a short count (< `max`) only means the current block ran out,
+/// not necessarily EOF. All pointers stay valid until the next call on the
+/// handle. An error after the first message ends the batch early and is
+/// recorded for `get_last_error_code`, which the call clears on entry, as
+/// in `read_up_to`: a strict‑time error has already consumed its message,
+/// so it would not come back on the next call.
+///
+/// # Safety
+/// `handle` must come from `open_reader`; both arrays must hold `max` entries.
+#[no_mangle]
+pub unsafe extern "C" fn read_message_batch(
+    handle: *mut c_void, out_ptrs: *mut *const c_void, out_sizes: *mut u16, max: usize,
+) -> i32 {
+    if handle.is_null() || out_ptrs.is_null() || out_sizes.is_null() { return -1; }
+    let rdr = &mut *(handle as *mut FastCacheReader);
+    LAST_ERROR.with(|l| *l.borrow_mut() = None);
+    let mut n = 0;
+    while n < max.min(i32::MAX as usize) {
+        let next = if n == 0 { rdr.next_range() } else { rdr.next_range_in_block() };
+        match next {
+            Ok(Some(r)) => {
+                *out_ptrs.add(n)  = rdr.inner.src.as_ptr().add(r.start) as *const c_void;
+                *out_sizes.add(n) = r.len() as u16;
+                n += 1;
+            }
+            Ok(None)            => break,
+            Err(e) if n == 0    => return set_last_error(e),
+            Err(e)              => { set_last_error(e); break; }
+        }
+    }
+    n as i32
+}
+
+/// Copies up to `max` whole messages, back to back, into `buf` and returns
+/// how many; `*out_len` gets the bytes used. Unlike `read_message_batch`
+/// this crosses block boundaries, since the bytes are copied: a count
+/// below `max` means the stream or the buffer ran out, or an error
+/// stopped the copy. A message that doesn't fit is left for the next
+/// call; if not even the first one fits, the call fails with
+/// `ERR_INVALID_ARGS`. 0 means end of stream. The call clears the last
+/// error on entry, so after a short count a nonzero
+/// `get_last_error_code` tells an error that came after the copied
+/// messages from the end of the stream.
+///
+/// # Safety
+/// `handle` must come from `open_reader`; `buf` must be writable for
+/// `buf_len` bytes and `out_len` a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn read_up_to(handle: *mut c_void, max: usize, buf: *mut u8, buf_len: usize, out_len: *mut usize) -> i32 {
+    if handle.is_null() || buf.is_null() || out_len.is_null() { return -1; }
+    let rdr = &mut *(handle as *mut FastCacheReader);
+    LAST_ERROR.with(|l| *l.borrow_mut() = None);
+    let (mut n, mut used) = (0, 0);
+    *out_len = 0;
+    while n < max.min(i32::MAX as usize) {
+        match rdr.advance() {
+            Ok(Some(r)) if used + r.len() > buf_len => {
+                rdr.offset = r.start;
+                if n > 0 { break; }
+                return set_last_error(FastStorageError::Invalid(format!(
+                    "message of {} bytes does not fit in a {buf_len} byte buffer", r.len()
+                )));
+            }
+            Ok(Some(r)) => {
+                if let Err(e) = rdr.mark(Some(r.clone())) {
+                    if n == 0 { return set_last_error(e); }
+                    set_last_error(e);
+                    break;
+                }
+                ptr::copy_nonoverlapping(rdr.inner.src.as_ptr().add(r.start), buf.add(used), r.len());
+                used += r.len();
+                n += 1;
+            }
+            Ok(None)         => break,
+            Err(e) if n == 0 => return set_last_error(e),
+            Err(e)           => { set_last_error(e); break; }
+        }
+    }
+    *out_len = used;
+    n as i32
+}
+
+/// Callback for [`process_file`]: the message (header included) and the
+/// caller's `user_data`.
+pub type MessageCallback = unsafe extern "C" fn(msg: *const c_void, user_data: *mut c_void);
+
+/// Reads all of `path`, calling `depth_cb` for every depth message and
+/// `tick_cb` for every trade, so the loop stays on the Rust side with no
+/// FFI round trip per message. Either callback may be null to skip that
+/// kind; other kinds are skipped. Returns the number of callbacks made,
+/// or an `ERR_*` code, in which case the callbacks made so far stand.
+///
+/// # Safety
+/// `path` must be a NUL‑terminated string. Each `msg` is valid only for
+/// the duration of its callback, and must not be kept past it.
+#[no_mangle]
+pub unsafe extern "C" fn process_file(
+    path: *const c_char, depth_cb: Option<MessageCallback>, tick_cb: Option<MessageCallback>, user_data: *mut c_void,
+) -> i64 {
+    if path.is_null() { return ERR_INVALID_ARGS as i64; }
+    let path = match c_path(path) {
+        Ok(p)  => p,
+        Err(e) => return set_last_error(e) as i64,
+    };
+    let mut rdr = match FastCacheReader::open(path) {
+        Ok(r)  => r,
+        Err(e) => return set_last_error(e) as i64,
+    };
+    let kinds: Vec<MessageKind> = [(depth_cb, MessageKind::Depth), (tick_cb, MessageKind::Tick)]
+        .into_iter().filter_map(|(cb, k)| cb.map(|_| k)).collect();
+    rdr.set_kind_filter(&kinds);
+    let mut n = 0i64;
+    loop {
+        match rdr.next_msg() {
+            Ok(Some(p)) => {
+                let cb = if ptr::read_unaligned(p as *const MessageHeader).kind == MessageKind::Depth as i16 { depth_cb } else { tick_cb };
+                if let Some(cb) = cb { cb(p, user_data); n += 1; }
+            }
+            Ok(None) => return n,
+            Err(e)   => return set_last_error(e) as i64,
+        }
+    }
+}
+
+/// Restricts `read_message`/`read_message_batch` to the `count` kinds at
+/// `kinds`; a null `kinds` clears the filter. Returns -1 for kinds outside
+/// 0..=31.
+///
+/// # Safety
+/// `handle` must come from `open_reader`; a non‑null `kinds` must point to
+/// `count` readable values.
+#[no_mangle]
+pub unsafe extern "C" fn set_kind_filter(handle: *mut c_void, kinds: *const i16, count: usize) -> i32 {
+    if handle.is_null() { return -1; }
+    let rdr = &mut *(handle as *mut FastCacheReader);
+    if kinds.is_null() { rdr.kinds = None; return 0; }
+    let kinds = std::slice::from_raw_parts(kinds, count);
+    if kinds.iter().any(|k| !(0..32).contains(k)) { return -1; }
+    rdr.kinds = Some(kinds.iter().fold(0, |m, &k| m | 1 << k));
+    0
+}
+
+/// Stores the reader's [`CompressionStats`] totals so far in `*blocks`,
+/// `*compressed` and `*decompressed`.
+///
+/// # Safety
+/// `handle` must come from `open_reader`; the three out pointers must be
+/// writable.
+#[no_mangle]
+pub unsafe extern "C" fn get_compression_stats(
+    handle: *mut c_void, blocks: *mut u64, compressed: *mut u64, decompressed: *mut u64,
+) -> i32 {
+    if handle.is_null() || blocks.is_null() || compressed.is_null() || decompressed.is_null() { return -1; }
+    let stats = (*(handle as *mut FastCacheReader)).compression_stats();
+    (*blocks, *compressed, *decompressed) = (stats.blocks, stats.compressed, stats.decompressed);
+    0
+}
+
+/// Header of the message most recently returned by `read_message`.
+unsafe fn last_header(handle: *mut c_void) -> Option<MessageHeader> {
+    if handle.is_null() { return None; }
+    (*(handle as *mut FastCacheReader)).last
+}
+
+/// Kind of the last message read; -1 if none has been read yet.
+///
+/// # Safety
+/// `handle` must come from `open_reader`.
+#[no_mangle]
+pub unsafe extern "C" fn last_kind(handle: *mut c_void) -> i16 {
+    last_header(handle).map_or(-1, |h| h.kind)
+}
+
+/// Timestamp of the last message read; -1 if none has been read yet.
+/// A message may itself carry time -1, so test `last_kind(handle) < 0`,
+/// not this value, to tell whether a message has been read.
+///
+/// # Safety
+/// `handle` must come from `open_reader`.
+#[no_mangle]
+pub unsafe extern "C" fn last_time(handle: *mut c_void) -> i64 {
+    last_header(handle).map_or(-1, |h| h.time)
+}
+
+/// Size of the last message read; -1 if none has been read yet.
+///
+/// # Safety
+/// `handle` must come from `open_reader`.
+#[no_mangle]
+pub unsafe extern "C" fn last_size(handle: *mut c_void) -> i32 {
+    last_header(handle).map_or(-1, |h| h.size as i32)
+}
+
+/// Kind of the message at `msg`, a pointer returned by `read_message` or
+/// `read_message_batch`; -1 for null.
+///
+/// # Safety
+/// `msg` must be null or such a pointer, still valid: it is invalidated
+/// by the next read on its handle.
+#[no_mangle]
+pub unsafe extern "C" fn message_kind(msg: *const c_void) -> i16 {
+    if msg.is_null() { return -1; }
+    ptr::read_unaligned(msg as *const MessageHeader).kind
+}
+
+/// Timestamp of the message at `msg`, as for `message_kind`; -1 for null.
+///
+/// # Safety
+/// As for `message_kind`.
+#[no_mangle]
+pub unsafe extern "C" fn message_time(msg: *const c_void) -> i64 {
+    if msg.is_null() { return -1; }
+    ptr::read_unaligned(msg as *const MessageHeader).time
+}
+
+/// Restarts `handle` at the first block, as [`FastCacheReader::rewind`]
+/// does, so a file can be replayed again without reopening it. Block
+/// buffers stay allocated; filters and other settings are kept. Pointers
+/// from earlier reads become invalid.
+///
+/// # Safety
+/// `handle` must come from `open_reader`.
+#[no_mangle]
+pub unsafe extern "C" fn reset_reader(handle: *mut c_void) -> i32 {
+    if handle.is_null() { return -1; }
+    match (*(handle as *mut FastCacheReader)).rewind() {
+        Ok(())  => 0,
+        Err(e)  => set_last_error(e),
+    }
+}
+
+/// # Safety
+/// `h` must come from `open_reader` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn close_reader(h: *mut c_void) {
+    if !h.is_null() {
+        drop(Box::from_raw(h as *mut FastCacheReader));
+    }
+}
+
+/// `compression` is a [`CompressionMode`] value: 0 = stored, 1 = LZ4.
+///
+/// # Safety
+/// `path` must be a NUL‑terminated string and `out` a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn open_writer(path: *const c_char, buffer_len: i32, compression: i32, out: *mut *mut c_void) -> i32 {
+    let mode = match compression {
+        0 => CompressionMode::Stored,
+        1 => CompressionMode::Lz4,
+        _ => return -1,
+    };
+    if path.is_null() || out.is_null() || buffer_len <= 0 { return -1; }
+    let path = match c_path(path) {
+        Ok(p)  => p,
+        Err(e) => return set_last_error(e),
+    };
+    match FastCacheWriter::create(path, buffer_len as usize, mode) {
+        Ok(w)  => { *out = Box::into_raw(Box::new(w)) as *mut _; 0 }
+        Err(e) => set_last_error(e),
+    }
+}
+
+/// Opens `path` for appending, as [`FastCacheWriter::append`]: an
+/// existing file keeps its buffer length, and `buffer_len` only applies
+/// if the file is missing or empty.
+///
+/// # Safety
+/// `path` must be a NUL‑terminated string and `out` a writable pointer.
+#[no_mangle]
+pub unsafe extern "C" fn open_writer_append(path: *const c_char, buffer_len: i32, compression: i32, out: *mut *mut c_void) -> i32 {
+    let mode = match compression {
+        0 => CompressionMode::Stored,
+        1 => CompressionMode::Lz4,
+        _ => return -1,
+    };
+    if path.is_null() || out.is_null() || buffer_len <= 0 { return -1; }
+    let path = match c_path(path) {
+        Ok(p)  => p,
+        Err(e) => return set_last_error(e),
+    };
+    match FastCacheWriter::append(path, buffer_len as usize, mode) {
+        Ok(w)  => { *out = Box::into_raw(Box::new(w)) as *mut _; 0 }
+        Err(e) => set_last_error(e),
+    }
+}
+
+/// Copies one message of `size` bytes into the writer. Returns 0 on
+/// success, or an `ERR_*` code if the message is malformed or the write fails.
+///
+/// # Safety
+/// `handle` must come from `open_writer`; `msg` must point to `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn write_message(handle: *mut c_void, msg: *const c_void, size: i32) -> i32 {
+    if handle.is_null() || msg.is_null() || size <= 0 { return -1; }
+    let wtr = &mut *(handle as *mut FastCacheWriter);
+    match wtr.write(std::slice::from_raw_parts(msg as *const u8, size as usize)) {
+        Ok(())  => 0,
+        Err(e)  => set_last_error(e),
+    }
+}
+
+/// Writes the messages staged so far as a block and flushes the file;
+/// see [`FastCacheWriter::flush`]. The writer stays open.
+///
+/// # Safety
+/// `handle` must come from `open_writer`.
+#[no_mangle]
+pub unsafe extern "C" fn flush_writer(handle: *mut c_void) -> i32 {
+    if handle.is_null() { return -1; }
+    match (*(handle as *mut FastCacheWriter)).flush() {
+        Ok(())  => 0,
+        Err(e)  => set_last_error(e),
+    }
+}
+
+/// Flushes the final block and closes the file.
+///
+/// # Safety
+/// `h` must come from `open_writer` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn close_writer(h: *mut c_void) -> i32 {
+    if h.is_null() { return -1; }
+    match Box::from_raw(h as *mut FastCacheWriter).finish() {
+        Ok(())  => 0,
+        Err(e)  => set_last_error(e),
+    }
+}
+
+/* ────────────────  4. order book  ──────────────────────────────────── */
+
+pub mod orderbook {
+    use super::*;
+    use ordered_float::OrderedFloat;
+    use std::collections::BTreeMap;
+    use std::io;
+
+    /// `(price, volume)`, scaled by the book's [`Scale`].
+    pub type Level = (f64, f64);
+
+    /// Price‑level book rebuilt from [`DepthItem`]s. Prices and volumes are
+    /// kept as floats divided by the book's [`Scale`] (1e8 unless
+    /// [`DepthBook::set_scale`] says otherwise); a level with volume 0 is
+    /// removed.
+    #[derive(Clone, Debug, Default)]
+    pub struct DepthBook {
+        bids:    BTreeMap<OrderedFloat<f64>, f64>,
+        asks:    BTreeMap<OrderedFloat<f64>, f64>,
+        atomic:  bool,
+        pending: Vec<DepthItem>,
+        scale:   Scale,
+        no_ops:  u64,
+    }
+
+    impl DepthBook {
+        /// Applies one level update. `CLEAR` empties both sides before the
+        /// level is set; `BUY` selects the bid side, anything else the ask
+        /// side. Returns whether the item carries `END_OF_TX`, i.e. whether
+        /// the book is consistent afterwards.
+        ///
+        /// In atomic mode the update is held back until an `END_OF_TX`
+        /// item arrives, and then the whole transaction is applied at once.
+        /// Between calls, the book never shows a half‑applied transaction.
+        pub fn apply(&mut self, item: &DepthItem) -> bool {
+            let end = item.market_flags().contains(MarketFlag::END_OF_TX);
+            if !self.atomic {
+                self.apply_level(item);
+                return end;
+            }
+            self.pending.push(*item);
+            if !end { return false; }
+            for item in std::mem::take(&mut self.pending) { self.apply_level(&item); }
+            true
+        }
+
+        /// [`apply`](Self::apply), but fails if the book is crossed or
+        /// locked afterwards while no transaction is open. Outside atomic
+        /// mode every update lands on its own, so every update is checked.
+        /// In atomic mode updates are held back until `END_OF_TX`, so the
+        /// book may cross inside a transaction without error and is checked
+        /// once the whole transaction is applied. The update is applied
+        /// either way.
+        pub fn apply_checked(&mut self, item: &DepthItem) -> Result<bool, CrossedBook> {
+            let end = self.apply(item);
+            if let (true, Some((bid, _)), Some((ask, _))) = (self.pending.is_empty(), self.best_bid(), self.best_ask()) {
+                if bid >= ask { return Err(CrossedBook { time: item.header.time, bid, ask }); }
+            }
+            Ok(end)
+        }
+
+        /// Best bid above best ask. False while either side is empty.
+        pub fn is_crossed(&self) -> bool {
+            matches!((self.best_bid(), self.best_ask()), (Some((bid, _)), Some((ask, _))) if bid > ask)
+        }
+
+        /// Best bid equal to best ask.
+        pub fn is_locked(&self) -> bool {
+            matches!((self.best_bid(), self.best_ask()), (Some((bid, _)), Some((ask, _))) if bid == ask)
+        }
+
+        /// Buffer updates per transaction (see [`DepthBook::apply`]). Leaving
+        /// atomic mode applies anything still buffered.
+        pub fn set_atomic(&mut self, atomic: bool) {
+            self.atomic = atomic;
+            if !atomic {
+                for item in std::mem::take(&mut self.pending) { self.apply_level(&item); }
+            }
+        }
+
+        /// Updates held back in atomic mode, waiting for `END_OF_TX`.
+        pub fn pending(&self) -> usize { self.pending.len() }
+
+        /// Updates applied so far that left the book as it was: a level set
+        /// to the volume it already had, or a missing level removed. Updates
+        /// carrying `CLEAR` never count. A measure of how much of a feed is
+        /// redundant.
+        pub fn redundant_updates(&self) -> u64 { self.no_ops }
+
+        /// Divisors for levels applied from now on; set it before the first
+        /// update, as levels already in the book are not rescaled.
+        pub fn set_scale(&mut self, scale: Scale) { self.scale = scale; }
+
+        fn apply_level(&mut self, item: &DepthItem) {
+            let flags = item.market_flags();
+            if flags.contains(MarketFlag::CLEAR) {
+                self.bids.clear();
+                self.asks.clear();
+            }
+            let side = if flags.contains(MarketFlag::BUY) { &mut self.bids } else { &mut self.asks };
+            let (price, volume) = (OrderedFloat(item.scaled_price(&self.scale)), item.scaled_volume(&self.scale));
+            let old = if volume > 0.0 { side.insert(price, volume) } else { side.remove(&price) };
+            if !flags.contains(MarketFlag::CLEAR) && old == (volume > 0.0).then_some(volume) { self.no_ops += 1; }
+        }
+
+        pub fn best_bid(&self) -> Option<Level> { self.bids.iter().next_back().map(|(p, v)| (p.0, *v)) }
+        pub fn best_ask(&self) -> Option<Level> { self.asks.iter().next().map(|(p, v)| (p.0, *v)) }
+
+        /// Up to `depth` best levels per side as `(bids, asks)`, best first.
+        pub fn top_n(&self, depth: usize) -> (Vec<Level>, Vec<Level>) {
+            (
+                self.bids.iter().rev().take(depth).map(|(p, v)| (p.0, *v)).collect(),
+                self.asks.iter().take(depth).map(|(p, v)| (p.0, *v)).collect(),
+            )
+        }
+
+        pub fn bid_levels(&self) -> usize { self.bids.len() }
+        pub fn ask_levels(&self) -> usize { self.asks.len() }
+
+        /// Top `levels` per side plus mid and spread. Sides with fewer
+        /// levels come back shorter; nothing is padded.
+        pub fn snapshot(&self, levels: usize) -> BookSnapshot {
+            let (bids, asks) = self.top_n(levels);
+            let (mid, spread) = match (self.best_bid(), self.best_ask()) {
+                (Some((bid, _)), Some((ask, _))) => (Some((bid + ask) / 2.0), Some(ask - bid)),
+                _                                => (None, None),
+            };
+            BookSnapshot { bids, asks, mid, spread }
+        }
+    }
+
+    /// `(price, volume)` in the raw fixed‑point units of the wire format.
+    pub type IntLevel = (i64, i64);
+
+    /// [`DepthBook`] on the raw `i64` prices and volumes, unscaled: levels
+    /// are keyed by exact price, so reconstruction is bit‑exact and free of
+    /// float ordering and rounding. Same update rules, atomic mode included.
+    #[derive(Clone, Debug, Default)]
+    pub struct IntDepthBook {
+        bids:    BTreeMap<i64, i64>,
+        asks:    BTreeMap<i64, i64>,
+        atomic:  bool,
+        pending: Vec<DepthItem>,
+        no_ops:  u64,
+    }
+
+    impl IntDepthBook {
+        /// See [`DepthBook::apply`].
+        pub fn apply(&mut self, item: &DepthItem) -> bool {
+            let end = item.market_flags().contains(MarketFlag::END_OF_TX);
+            if !self.atomic {
+                self.apply_level(item);
+                return end;
+            }
+            self.pending.push(*item);
+            if !end { return false; }
+            for item in std::mem::take(&mut self.pending) { self.apply_level(&item); }
+            true
+        }
+
+        /// See [`DepthBook::apply_checked`]; prices come back unscaled.
+        pub fn apply_checked(&mut self, item: &DepthItem) -> Result<bool, CrossedBook<i64>> {
+            let end = self.apply(item);
+            if let (true, Some((bid, _)), Some((ask, _))) = (self.pending.is_empty(), self.best_bid(), self.best_ask()) {
+                if bid >= ask { return Err(CrossedBook { time: item.header.time, bid, ask }); }
+            }
+            Ok(end)
+        }
+
+        /// See [`DepthBook::set_atomic`].
+        pub fn set_atomic(&mut self, atomic: bool) {
+            self.atomic = atomic;
+            if !atomic {
+                for item in std::mem::take(&mut self.pending) { self.apply_level(&item); }
+            }
+        }
+
+        pub fn pending(&self) -> usize { self.pending.len() }
+
+        /// See [`DepthBook::redundant_updates`].
+        pub fn redundant_updates(&self) -> u64 { self.no_ops }
+
+        fn apply_level(&mut self, item: &DepthItem) {
+            let flags = item.market_flags();
+            if flags.contains(MarketFlag::CLEAR) {
+                self.bids.clear();
+                self.asks.clear();
+            }
+            let side = if flags.contains(MarketFlag::BUY) { &mut self.bids } else { &mut self.asks };
+            let (price, volume) = (item.price(), item.volume());
+            let old = if volume > 0 { side.insert(price, volume) } else { side.remove(&price) };
+            if !flags.contains(MarketFlag::CLEAR) && old == (volume > 0).then_some(volume) { self.no_ops += 1; }
+        }
+
+        pub fn best_bid(&self) -> Option<IntLevel> { self.bids.iter().next_back().map(|(&p, &v)| (p, v)) }
+        pub fn best_ask(&self) -> Option<IntLevel> { self.asks.iter().next().map(|(&p, &v)| (p, v)) }
+
+        /// Up to `depth` best levels per side as `(bids, asks)`, best first.
+        pub fn top_n(&self, depth: usize) -> (Vec<IntLevel>, Vec<IntLevel>) {
+            (
+                self.bids.iter().rev().take(depth).map(|(&p, &v)| (p, v)).collect(),
+                self.asks.iter().take(depth).map(|(&p, &v)| (p, v)).collect(),
+            )
+        }
+
+        pub fn bid_levels(&self) -> usize { self.bids.len() }
+        pub fn ask_levels(&self) -> usize { self.asks.len() }
+
+        /// See [`DepthBook::is_crossed`].
+        pub fn is_crossed(&self) -> bool {
+            matches!((self.best_bid(), self.best_ask()), (Some((bid, _)), Some((ask, _))) if bid > ask)
+        }
+
+        /// See [`DepthBook::is_locked`].
+        pub fn is_locked(&self) -> bool {
+            matches!((self.best_bid(), self.best_ask()), (Some((bid, _)), Some((ask, _))) if bid == ask)
+        }
+    }
+
+    /// Returned by [`DepthBook::apply_checked`] (`f64` prices) and
+    /// [`IntDepthBook::apply_checked`] (raw `i64`): the book was crossed
+    /// (`bid > ask`) or locked (`bid == ask`) after the update at `time`.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct CrossedBook<P = f64> { pub time: i64, pub bid: P, pub ask: P }
+
+    impl<P: PartialEq + std::fmt::Display> std::fmt::Display for CrossedBook<P> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let what = if self.bid == self.ask { "locked" } else { "crossed" };
+            write!(f, "book {what} at time {}: bid {} ask {}", self.time, self.bid, self.ask)
+        }
+    }
+
+    impl<P: PartialEq + std::fmt::Display + std::fmt::Debug> std::error::Error for CrossedBook<P> {}
+
+    /// Layout of [`BestBookCsv`]. The default is the historical
+    /// `best_book.csv`: one level, `;`‑separated.
+    #[derive(Clone, Copy, Debug)]
+    pub struct CsvOptions {
+        pub levels:    usize,
+        pub delimiter: char,
+    }
+
+    impl Default for CsvOptions {
+        fn default() -> Self { Self { levels: 1, delimiter: ';' } }
+    }
+
+    /// Writes top‑of‑book rows: `time`, then price and volume for each ask
+    /// level, then for each bid level, at 8 decimals. With one level the
+    /// columns are `bestAskPrice;bestAskVolume;bestBidPrice;bestBidVolume`,
+    /// otherwise `askPrice1;askVolume1;…;bidPrice1;…`. Missing levels are
+    /// empty cells.
+    pub struct BestBookCsv<W: Write> { w: W, opts: CsvOptions }
+
+    impl<W: Write> BestBookCsv<W> {
+        /// Writes the header row.
+        pub fn new(mut w: W, opts: CsvOptions) -> Result<Self> {
+            let d = opts.delimiter;
+            let mut head = String::from("time");
+            if opts.levels == 1 {
+                for col in ["bestAskPrice", "bestAskVolume", "bestBidPrice", "bestBidVolume"] {
+                    head.push(d);
+                    head.push_str(col);
+                }
+            } else {
+                for side in ["ask", "bid"] {
+                    for i in 1..=opts.levels { head.push_str(&format!("{d}{side}Price{i}{d}{side}Volume{i}")); }
+                }
+            }
+            writeln!(w, "{head}")?;
+            Ok(Self { w, opts })
+        }
+
+        /// One row for the book as of `ts`.
+        pub fn row(&mut self, ts: i64, book: &DepthBook) -> Result<()> {
+            let (d, n) = (self.opts.delimiter, self.opts.levels);
+            let (bids, asks) = book.top_n(n);
+            write!(self.w, "{ts}")?;
+            for side in [&asks, &bids] {
+                for i in 0..n {
+                    match side.get(i) {
+                        Some((p, v)) => write!(self.w, "{d}{p:.8}{d}{v:.8}")?,
+                        None         => write!(self.w, "{d}{d}")?,
+                    }
+                }
+            }
+            writeln!(self.w)?;
+            Ok(())
+        }
+
+        pub fn into_inner(self) -> W { self.w }
+    }
+
+    /// Replays `reader` and writes a [`BestBookCsv`] row after every depth
+    /// update that leaves both sides non‑empty. Rows start at the first
+    /// trade: before it the book is still being built from the initial
+    /// snapshot. Returns the number of rows written.
+    pub fn export_best_book_csv<R: Read, W: Write>(reader: FastCacheReader<R>, out: W, opts: CsvOptions) -> Result<u64> {
+        let mut csv = BestBookCsv::new(out, opts)?;
+        let mut book = DepthBook::default();
+        let mut msgs = reader.messages();
+        let mut building_snapshot = true;
+        let mut rows = 0;
+        while let Some(msg) = msgs.next_message()? {
+            match msg.message_kind() {
+                Some(MessageKind::Depth) => {
+                    let Some(d) = msg.as_depth() else { continue };
+                    book.apply(&d);
+                    if !building_snapshot && book.best_bid().is_some() && book.best_ask().is_some() {
+                        csv.row(d.header.time, &book)?;
+                        rows += 1;
+                    }
+                }
+                Some(MessageKind::Tick) => building_snapshot = false,
+                _ => {}
+            }
+        }
+        csv.into_inner().flush()?;
+        Ok(rows)
+    }
+
+    /// Writes one `;`‑separated timeline of trades and best‑book changes,
+    /// in the order they are fed. Columns are
+    /// `time;type;price;volume;side;bestAskPrice;bestAskVolume;bestBidPrice;bestBidVolume`;
+    /// `trade` rows fill the first three, `book` rows the last four, at
+    /// 8 decimals.
+    pub struct TimelineCsv<W: Write> { w: W, last: Option<(Level, Level)> }
+
+    impl<W: Write> TimelineCsv<W> {
+        /// Writes the header row.
+        pub fn new(mut w: W) -> io::Result<Self> {
+            writeln!(w, "time;type;price;volume;side;bestAskPrice;bestAskVolume;bestBidPrice;bestBidVolume")?;
+            Ok(Self { w, last: None })
+        }
+
+        /// A `trade` row; the side is `buy`, `sell` or empty.
+        pub fn trade(&mut self, ts: i64, price: f64, volume: f64, side: Side) -> io::Result<()> {
+            let side = match side {
+                Side::Buy     => "buy",
+                Side::Sell    => "sell",
+                Side::Unknown => "",
+            };
+            writeln!(self.w, "{ts};trade;{price:.8};{volume:.8};{side};;;;")
+        }
+
+        /// A `book` row, written only when the best ask or bid changed
+        /// since the last one. Returns whether it was written.
+        pub fn book(&mut self, ts: i64, ask: Level, bid: Level) -> io::Result<bool> {
+            if self.last == Some((ask, bid)) { return Ok(false); }
+            self.last = Some((ask, bid));
+            writeln!(self.w, "{ts};book;;;;{:.8};{:.8};{:.8};{:.8}", ask.0, ask.1, bid.0, bid.1)?;
+            Ok(true)
+        }
+
+        pub fn into_inner(self) -> W { self.w }
+    }
+
+    /// Replays `reader` into a [`TimelineCsv`]: every trade, and a `book`
+    /// row whenever a depth update changes the top of a two‑sided book.
+    /// As in [`export_best_book_csv`], book rows start at the first trade.
+    /// Rows follow the file's message order and are not sorted, so a feed
+    /// whose clock steps back yields a `time` column that does too. Prices
+    /// and volumes are divided by `scale`. Returns the number of rows
+    /// written.
+    pub fn export_timeline_csv<R: Read, W: Write>(reader: FastCacheReader<R>, out: W, scale: &Scale) -> Result<u64> {
+        let mut tl = TimelineCsv::new(out)?;
+        let mut book = DepthBook::default();
+        book.set_scale(*scale);
+        let mut msgs = reader.messages();
+        let mut building_snapshot = true;
+        let mut rows = 0;
+        while let Some(msg) = msgs.next_message()? {
+            match msg.message_kind() {
+                Some(MessageKind::Depth) => {
+                    let Some(d) = msg.as_depth() else { continue };
+                    book.apply(&d);
+                    if building_snapshot { continue; }
+                    if let (Some(ask), Some(bid)) = (book.best_ask(), book.best_bid()) {
+                        if tl.book(d.header.time, ask, bid)? { rows += 1; }
+                    }
+                }
+                Some(MessageKind::Tick) => {
+                    let Some(t) = msg.as_tick() else { continue };
+                    tl.trade(t.header.time, t.scaled_price(scale), t.scaled_volume(scale), t.side())?;
+                    rows += 1;
+                    building_snapshot = false;
+                }
+                _ => {}
+            }
+        }
+        tl.into_inner().flush()?;
+        Ok(rows)
+    }
+
+    /// Ladder returned by [`DepthBook::snapshot`]; both sides best first.
+    /// `mid` and `spread` are `None` unless both sides have a level.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct BookSnapshot {
+        pub bids:   Vec<Level>,
+        pub asks:   Vec<Level>,
+        pub mid:    Option<f64>,
+        pub spread: Option<f64>,
+    }
+
+    /// Full [`DepthBook`] as it stood just before block `block`: every
+    /// depth update of the earlier blocks applied, in non‑atomic mode and
+    /// at the default [`Scale`].
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct BookCheckpoint {
+        /// Time of the block's first message, as in [`BlockIndexEntry::first_time`].
+        pub time:     i64,
+        /// File offset of the block's length prefix.
+        pub file_pos: u64,
+        /// Index of the block in the file, counting from 0.
+        pub block:    u64,
+        bids:         Vec<Level>,
+        asks:         Vec<Level>,
+    }
+
+    impl BookCheckpoint {
+        /// The book this checkpoint holds.
+        pub fn book(&self) -> DepthBook {
+            let side = |levels: &[Level]| levels.iter().map(|&(p, v)| (OrderedFloat(p), v)).collect();
+            DepthBook { bids: side(&self.bids), asks: side(&self.asks), ..DepthBook::default() }
+        }
+    }
+
+    /// Book snapshots taken every few blocks of a file, so
+    /// [`BookCheckpoints::seek`] can rebuild the book at any time by
+    /// replaying from the nearest earlier snapshot instead of from the
+    /// start of the file.
+    ///
+    /// On disk (all little‑endian): magic `FSBK`, `u32` version (1), `u64`
+    /// length of the file, `u64` checkpoint count, then per checkpoint
+    /// `i64 time, u64 file_pos, u64 block, u32 bid count, u32 ask count`
+    /// followed by the bids and then the asks as `f64 price, f64 volume`
+    /// pairs in ascending price order.
+    #[derive(Clone, Debug, Default)]
+    pub struct BookCheckpoints { file_len: u64, checkpoints: Vec<BookCheckpoint> }
+
+    impl BookCheckpoints {
+        const MAGIC: &'static [u8; 4] = b"FSBK";
+        const VERSION: u32 = 1;
+
+        /// Replays `path` once, snapshotting the book before every
+        /// `every`‑th block (blocks `every`, `2 * every`, …).
+        pub fn build(path: &str, every: usize) -> Result<Self> {
+            ensure!(every > 0, "checkpoint interval must be at least one block");
+            let mut rdr = BlockReader::open(path)?;
+            let mut book = DepthBook::default();
+            let mut checkpoints = Vec::new();
+            let (mut block, mut time) = (0u64, i64::MIN);
+            while let Some(b) = rdr.next_block()? {
+                if let Some(h) = b.bytes.get(..size_of::<MessageHeader>()).map(|b| unsafe { MessageRef::from_framed(b) }.header()) {
+                    if h.size != 0 { time = h.time; }
+                }
+                if block > 0 && block % every as u64 == 0 {
+                    let side = |levels: &BTreeMap<OrderedFloat<f64>, f64>| levels.iter().map(|(p, v)| (p.0, *v)).collect();
+                    checkpoints.push(BookCheckpoint {
+                        time, file_pos: b.file_pos, block, bids: side(&book.bids), asks: side(&book.asks),
+                    });
+                }
+                for msg in BlockMessages::new(b.bytes) {
+                    if let Some(d) = msg?.as_depth() { book.apply(&d); }
+                }
+                block += 1;
+                if has_terminator(b.bytes) { break; }
+            }
+            Ok(Self { file_len: rdr.file_len.unwrap_or(rdr.file_pos), checkpoints })
+        }
+
+        /// `<path>.book`, where [`BookCheckpoints::load_for`] looks.
+        pub fn sidecar_path(path: &str) -> String { format!("{path}.book") }
+
+        pub fn checkpoints(&self) -> &[BookCheckpoint] { &self.checkpoints }
+
+        /// Last checkpoint whose block starts before `ts`: everything
+        /// before it is older than `ts`.
+        pub fn nearest(&self, ts: i64) -> Option<&BookCheckpoint> {
+            let i = self.checkpoints.partition_point(|c| c.time < ts);
+            i.checked_sub(1).map(|i| &self.checkpoints[i])
+        }
+
+        /// Rebuilds the book as of `ts`, every depth update with
+        /// `header.time < ts` applied, and leaves `rdr` at the first
+        /// message with `header.time >= ts`, as
+        /// [`FastCacheReader::seek_to_time`] would. `rdr` must read the file
+        /// the checkpoints were built from; it is repositioned wherever it
+        /// was, and its kind and time filters don't apply to the replay.
+        pub fn seek<R: Read + Seek>(&self, rdr: &mut FastCacheReader<R>, ts: i64) -> Result<DepthBook> {
+            let mut book = match self.nearest(ts) {
+                Some(c) => {
+                    rdr.rewind()?;
+                    rdr.inner.jump(c.file_pos, c.block, R::seek)?;
+                    c.book()
+                }
+                None => { rdr.rewind()?; DepthBook::default() }
+            };
+            while let Some(h) = rdr.peek_header()? {
+                if h.time >= ts { break; }
+                match rdr.take_message() {
+                    Ok(Some(r)) => if let Some(d) = unsafe { MessageRef::from_framed(&rdr.inner.block()[r]) }.as_depth() { book.apply(&d); },
+                    Ok(None)    => break,
+                    Err(e)      => rdr.recover(e)?,
+                }
+            }
+            Ok(book)
+        }
+
+        pub fn save(&self, path: &str) -> Result<()> {
+            let mut out = BufWriter::new(File::create(path).with_context(|| format!("create {path}"))?);
+            out.write_all(Self::MAGIC)?;
+            out.write_all(&Self::VERSION.to_le_bytes())?;
+            out.write_all(&self.file_len.to_le_bytes())?;
+            out.write_all(&(self.checkpoints.len() as u64).to_le_bytes())?;
+            for c in &self.checkpoints {
+                out.write_all(&c.time.to_le_bytes())?;
+                out.write_all(&c.file_pos.to_le_bytes())?;
+                out.write_all(&c.block.to_le_bytes())?;
+                out.write_all(&(c.bids.len() as u32).to_le_bytes())?;
+                out.write_all(&(c.asks.len() as u32).to_le_bytes())?;
+                for &(p, v) in c.bids.iter().chain(&c.asks) {
+                    out.write_all(&p.to_le_bytes())?;
+                    out.write_all(&v.to_le_bytes())?;
+                }
+            }
+            out.flush()?;
+            Ok(())
+        }
+
+        pub fn load(path: &str) -> Result<Self> {
+            let data = std::fs::read(path).with_context(|| format!("read {path}"))?;
+            ensure!(data.len() >= 24 && &data[..4] == Self::MAGIC, "{path} is not a book checkpoint file");
+            let version = LittleEndian::read_u32(&data[4..]);
+            ensure!(version == Self::VERSION, "unsupported book checkpoint version {version}");
+            let file_len = LittleEndian::read_u64(&data[8..]);
+            let count = LittleEndian::read_u64(&data[16..]);
+            let mut body = &data[24..];
+            let mut checkpoints = Vec::new();
+            for i in 0..count {
+                ensure!(body.len() >= 32, "book checkpoint {i} truncated");
+                let (bids, asks) = (LittleEndian::read_u32(&body[24..]) as usize, LittleEndian::read_u32(&body[28..]) as usize);
+                let levels_len = (bids + asks) * 16;
+                ensure!(body.len() - 32 >= levels_len, "book checkpoint {i} truncated");
+                let mut levels = body[32..32 + levels_len].chunks_exact(16)
+                    .map(|l| (LittleEndian::read_f64(l), LittleEndian::read_f64(&l[8..])));
+                checkpoints.push(BookCheckpoint {
+                    time:     LittleEndian::read_i64(body),
+                    file_pos: LittleEndian::read_u64(&body[8..]),
+                    block:    LittleEndian::read_u64(&body[16..]),
+                    bids:     levels.by_ref().take(bids).collect(),
+                    asks:     levels.collect(),
+                });
+                body = &body[32 + levels_len..];
+            }
+            ensure!(body.is_empty(), "{} trailing bytes after book checkpoints", body.len());
+            Ok(Self { file_len, checkpoints })
+        }
+
+        /// Loads the sidecar of `path` ([`BookCheckpoints::sidecar_path`]).
+        /// Fails if it is missing or was built for a file of a different
+        /// length.
+        pub fn load_for(path: &str) -> Result<Self> {
+            let cps = Self::load(&Self::sidecar_path(path))?;
+            let len = std::fs::metadata(path).with_context(|| format!("stat {path}"))?.len();
+            ensure!(cps.file_len == len, "book checkpoints are stale: built for {} bytes, file has {len}", cps.file_len);
+            Ok(cps)
+        }
+    }
+}
+
+/* ────────────────  5. aggregation  ─────────────────────────────────── */
+
+pub mod aggregator {
+    use super::*;
+
+    /// What [`CandleBuilder`] emits for intervals without ticks.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum GapFill {
+        /// No candle at all.
+        #[default]
+        Skip,
+        /// A zero‑volume candle at the previous close.
+        Flat,
+    }
+
+    /// Folds ticks into fixed‑interval OHLCV candles, bucketed by
+    /// `header.time`. Candles use the same fixed‑point ×1e8 values and
+    /// .NET tick timestamps as the ticks, so they compare directly with
+    /// stored [`CandleItem`]s; each is stamped with the start of its
+    /// interval. Intervals are aligned to the Unix epoch, so one‑minute
+    /// candles start on the minute.
+    ///
+    /// Ticks should arrive in time order. One older than the open interval
+    /// is folded into it, since earlier candles have already been emitted.
+    #[derive(Clone, Debug)]
+    pub struct CandleBuilder {
+        interval: i64,   // in .NET ticks
+        gaps:     GapFill,
+        cur:      Option<CandleItem>,
+    }
+
+    impl CandleBuilder {
+        /// `interval_ns` is in nanoseconds and must be a positive multiple
+        /// of 100, the resolution of `header.time`.
+        pub fn new(interval_ns: i64) -> Result<Self> {
+            ensure!(interval_ns > 0, "candle interval must be positive, got {interval_ns} ns");
+            ensure!(interval_ns % 100 == 0, "candle interval {interval_ns} ns is not a whole number of 100 ns ticks");
+            Ok(Self { interval: interval_ns / 100, gaps: GapFill::default(), cur: None })
+        }
+
+        pub fn set_gap_fill(&mut self, gaps: GapFill) { self.gaps = gaps; }
+
+        /// Adds a tick, appending to `out` every candle it closes.
+        pub fn push(&mut self, tick: &TickItem, out: &mut Vec<CandleItem>) {
+            let (time, price, volume) = (tick.header.time, tick.price(), tick.volume());
+            let since_epoch = time.wrapping_sub(DOTNET_UNIX_EPOCH_TICKS);
+            let bucket = time.wrapping_sub(since_epoch.rem_euclid(self.interval));
+            match &mut self.cur {
+                Some(c) if bucket <= c.header.time => {
+                    *c = CandleItem::new(c.header.time, c.open(), c.high().max(price), c.low().min(price), price,
+                                         c.volume().saturating_add(volume));
+                    return;
+                }
+                _ => {}
+            }
+            if let Some(done) = self.cur.take() {
+                out.push(done);
+                if self.gaps == GapFill::Flat {
+                    let mut t = done.header.time + self.interval;
+                    while t < bucket {
+                        out.push(CandleItem::new(t, done.close(), done.close(), done.close(), done.close(), 0));
+                        t += self.interval;
+                    }
+                }
+            }
+            self.cur = Some(CandleItem::new(bucket, price, price, price, price, volume));
+        }
+
+        /// The open, partial candle, if any ticks arrived since the last one closed.
+        pub fn finish(self) -> Option<CandleItem> { self.cur }
+    }
+
+    /// Running per‑side trade totals, fed one [`TickItem`] at a time. Sums
+    /// are kept in raw fixed point, so nothing is lost to rounding until
+    /// [`TradeStats::finish`] scales them.
+    #[derive(Clone, Debug, Default)]
+    pub struct TradeStats {
+        scale:       Scale,
+        buy_volume:  i128,
+        sell_volume: i128,
+        buy_trades:  u64,
+        sell_trades: u64,
+        other:       u64,
+        notional:    i128,   // Σ price × volume, both raw
+        volume:      i128,
+        last:        Option<TickItem>,
+    }
+
+    /// What [`TradeStats::finish`] returns. Volumes and prices are scaled.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct TradeSummary {
+        pub buy_volume:   f64,
+        pub sell_volume:  f64,
+        pub buy_trades:   u64,
+        pub sell_trades:  u64,
+        /// Ticks whose side is [`Side::Unknown`]; they still count towards `vwap`.
+        pub other_trades: u64,
+        /// Volume‑weighted average price; `None` until some volume traded.
+        pub vwap:         Option<f64>,
+        pub last:         Option<TickItem>,
+    }
+
+    impl TradeSummary {
+        pub fn trades(&self) -> u64 { self.buy_trades + self.sell_trades + self.other_trades }
+    }
+
+    impl TradeStats {
+        pub fn new() -> Self { Self::default() }
+
+        /// Divisors used by [`TradeStats::finish`]; 1e8 by default.
+        pub fn set_scale(&mut self, scale: Scale) { self.scale = scale; }
+
+        pub fn push(&mut self, tick: &TickItem) {
+            let (price, volume) = (tick.price() as i128, tick.volume() as i128);
+            match tick.side() {
+                Side::Buy     => { self.buy_volume += volume; self.buy_trades += 1; }
+                Side::Sell    => { self.sell_volume += volume; self.sell_trades += 1; }
+                Side::Unknown => self.other += 1,
+            }
+            self.notional += price * volume;
+            self.volume += volume;
+            self.last = Some(*tick);
+        }
+
+        pub fn finish(self) -> TradeSummary {
+            let s = self.scale;
+            TradeSummary {
+                buy_volume:   self.buy_volume as f64 / s.volume_divisor,
+                sell_volume:  self.sell_volume as f64 / s.volume_divisor,
+                buy_trades:   self.buy_trades,
+                sell_trades:  self.sell_trades,
+                other_trades: self.other,
+                vwap:         (self.volume != 0).then(|| self.notional as f64 / self.volume as f64 / s.price_divisor),
+                last:         self.last,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmp(name: &str) -> String {
+        std::env::temp_dir().join(format!("faststorage-unit-{}-{name}", std::process::id())).to_string_lossy().into_owned()
+    }
+
+    fn depth_file(name: &str, times: &[i64]) -> String {
+        let path = tmp(name);
+        let mut w = FastCacheWriter::create(&path, 4096, CompressionMode::Lz4).unwrap();
+        for &t in times { w.write_typed(&Message::Depth(DepthItem::new(t, 100, 1, 1))).unwrap(); }
+        w.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn batch_reports_strict_time_error_after_partial_batch() {
+        let path = depth_file("batch-strict", &[10, 20, 5, 30]);
+        let mut rdr = FastCacheReader::open(&path).unwrap();
+        rdr.set_strict_time(true);
+        let h = Box::into_raw(Box::new(rdr)) as *mut c_void;
+        let (mut ptrs, mut sizes) = ([ptr::null(); 8], [0u16; 8]);
+        let time = |p: *const c_void| unsafe { ptr::read_unaligned(p as *const MessageHeader) }.time;
+        unsafe {
+            assert_eq!(read_message_batch(h, ptrs.as_mut_ptr(), sizes.as_mut_ptr(), 8), 2);
+            assert_eq!((time(ptrs[0]), time(ptrs[1])), (10, 20));
+            assert_eq!(get_last_error_code(), ERR_CORRUPT_MESSAGE);
+
+            assert_eq!(read_message_batch(h, ptrs.as_mut_ptr(), sizes.as_mut_ptr(), 8), 1);
+            assert_eq!(time(ptrs[0]), 30);
+            assert_eq!(get_last_error_code(), 0);
+            close_reader(h);
+        }
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,66 @@
+use faststorage_native::aggregator::*;
+use faststorage_native::*;
+
+const SECOND: i64 = 10_000_000;   // .NET ticks
+
+fn at(secs: i64, price: i64, volume: i64) -> TickItem {
+    TickItem::new(DOTNET_UNIX_EPOCH_TICKS + secs * SECOND, 0, price, volume, MarketFlag::BUY.bits())
+}
+
+fn ohlcv(c: &CandleItem) -> (i64, i64, i64, i64, i64, i64) {
+    ((c.header.time - DOTNET_UNIX_EPOCH_TICKS) / SECOND, c.open(), c.high(), c.low(), c.close(), c.volume())
+}
+
+#[test]
+fn buckets_ticks_by_interval_in_nanoseconds() {
+    let mut b = CandleBuilder::new(60_000_000_000).unwrap();
+    let mut out = Vec::new();
+    for t in [at(61, 5, 1), at(90, 7, 2), at(119, 4, 3), at(120, 6, 1), at(150, 8, 1)] { b.push(&t, &mut out); }
+    assert_eq!(out.iter().map(ohlcv).collect::<Vec<_>>(), [(60, 5, 7, 4, 4, 6)]);
+    assert_eq!(ohlcv(&b.finish().unwrap()), (120, 6, 8, 6, 8, 2));
+}
+
+#[test]
+fn gap_fill_modes() {
+    let ticks = [at(0, 5, 1), at(185, 9, 1)];
+    let mut skip = CandleBuilder::new(60_000_000_000).unwrap();
+    let mut flat = skip.clone();
+    flat.set_gap_fill(GapFill::Flat);
+    let (mut a, mut b) = (Vec::new(), Vec::new());
+    for t in &ticks { skip.push(t, &mut a); flat.push(t, &mut b); }
+    assert_eq!(a.iter().map(ohlcv).collect::<Vec<_>>(), [(0, 5, 5, 5, 5, 1)]);
+    assert_eq!(b.iter().map(ohlcv).collect::<Vec<_>>(), [(0, 5, 5, 5, 5, 1), (60, 5, 5, 5, 5, 0), (120, 5, 5, 5, 5, 0)]);
+    assert_eq!(ohlcv(&flat.finish().unwrap()), (180, 9, 9, 9, 9, 1));
+}
+
+#[test]
+fn rejects_bad_interval() {
+    for ns in [0, -60_000_000_000, 150] {
+        let err = CandleBuilder::new(ns).unwrap_err();
+        assert!(matches!(err, FastStorageError::Invalid(_)), "{ns}: {err}");
+    }
+}
+
+#[test]
+fn trade_stats_split_by_side() {
+    const E8: i64 = 100_000_000;
+    let trade = |time, price: i64, volume: i64, side: MarketFlag| TickItem::new(time, time, price * E8, volume * E8, side.bits());
+    let ticks = [
+        trade(1, 100, 2, MarketFlag::BUY),
+        trade(2, 101, 3, MarketFlag::SELL),
+        trade(3, 102, 1, MarketFlag::BUY),
+        trade(4, 99, 4, MarketFlag::empty()),
+    ];
+    let mut stats = TradeStats::new();
+    for t in &ticks { stats.push(t); }
+    let s = stats.finish();
+
+    assert_eq!((s.buy_volume, s.sell_volume), (3.0, 3.0));
+    assert_eq!((s.buy_trades, s.sell_trades, s.other_trades, s.trades()), (2, 1, 1, 4));
+    // (100·2 + 101·3 + 102·1 + 99·4) / 10
+    assert!((s.vwap.unwrap() - 100.1).abs() < 1e-9, "{:?}", s.vwap);
+    assert_eq!(s.last.map(|t| t.id()), Some(4));
+
+    let empty = TradeStats::new().finish();
+    assert_eq!((empty.trades(), empty.vwap, empty.last.is_none()), (0, None, true));
+}
@@ -0,0 +1,104 @@
+//! Fixture builders shared by the integration tests.
+#![allow(dead_code)]
+
+use faststorage_native::*;
+
+/// A fresh path under the temp dir, unique to this test process and `name`.
+pub fn tmp(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("faststorage-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
+
+pub fn encode(msg: &Message) -> Vec<u8> {
+    let mut out = Vec::new();
+    msg.encode_into(&mut out);
+    out
+}
+
+pub fn depth(time: i64, price: i64, volume: i64, flags: MarketFlag) -> Vec<u8> {
+    encode(&Message::Depth(DepthItem::new(time, price, volume, flags.bits())))
+}
+
+pub fn tick(time: i64, id: i64, price: i64, volume: i64, side: MarketFlag) -> Vec<u8> {
+    encode(&Message::Tick(TickItem::new(time, id, price, volume, side.bits())))
+}
+
+pub fn symbol(time: i64, name: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(MessageKind::Symbol as i16).to_le_bytes());
+    out.extend_from_slice(&((12 + name.len()) as u16).to_le_bytes());
+    out.extend_from_slice(&time.to_le_bytes());
+    out.extend_from_slice(name);
+    out
+}
+
+/// Depth updates at the given times, one per message.
+pub fn depths(times: &[i64]) -> Vec<u8> {
+    times.iter().flat_map(|&t| depth(t, 10_000_000_000, 100_000_000, MarketFlag::BUY)).collect()
+}
+
+/// A file image whose blocks hold exactly `blocks`, each LZ4‑pickled.
+pub fn image(buf_len: i32, blocks: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = buf_len.to_le_bytes().to_vec();
+    for b in blocks { push_frame(&mut out, &k4os_pickler::pickle(b).unwrap()); }
+    out
+}
+
+/// Appends a length‑prefixed frame holding `pickled`.
+pub fn push_frame(out: &mut Vec<u8>, pickled: &[u8]) {
+    out.extend_from_slice(&(pickled.len() as i32).to_le_bytes());
+    out.extend_from_slice(pickled);
+}
+
+pub fn write_image(name: &str, buf_len: i32, blocks: &[Vec<u8>]) -> String {
+    let path = tmp(name);
+    std::fs::write(&path, image(buf_len, blocks)).unwrap();
+    path
+}
+
+/// Every message left in `rdr`, as raw bytes.
+pub fn drain<R: std::io::Read>(rdr: FastCacheReader<R>) -> Vec<Vec<u8>> {
+    let mut msgs = rdr.messages();
+    let mut out = Vec::new();
+    while let Some(m) = msgs.next_message().unwrap() { out.push(m.bytes().to_vec()); }
+    out
+}
+
+pub fn times<R: std::io::Read>(rdr: FastCacheReader<R>) -> Vec<i64> {
+    drain(rdr).iter().map(|m| i64::from_le_bytes(m[4..12].try_into().unwrap())).collect()
+}
+
+/// A pickled block whose LZ4 payload is garbage: it fails to decompress.
+pub fn corrupt_block() -> Vec<u8> {
+    let mut out = vec![1 << 6, 32];
+    out.extend_from_slice(&[0xff; 16]);
+    out
+}
+
+/// A file image of `blocks`, each pickled unless `None`, which stands for
+/// a [`corrupt_block`].
+pub fn image_with_corrupt(buf_len: i32, blocks: &[Option<Vec<u8>>]) -> Vec<u8> {
+    let mut out = buf_len.to_le_bytes().to_vec();
+    for b in blocks {
+        match b {
+            Some(b) => push_frame(&mut out, &k4os_pickler::pickle(b).unwrap()),
+            None    => push_frame(&mut out, &corrupt_block()),
+        }
+    }
+    out
+}
+
+/// Times of the messages read before the first error, which must come.
+pub fn times_until_error<R: std::io::Read>(rdr: &mut FastCacheReader<R>) -> Vec<i64> {
+    let mut out = Vec::new();
+    loop {
+        match rdr.next_typed() {
+            Ok(Some(m)) => out.push(time_of(&m)),
+            Ok(None)    => panic!("no error after {out:?}"),
+            Err(_)      => return out,
+        }
+    }
+}
+
+pub fn time_of(msg: &Message) -> i64 { i64::from_le_bytes(encode(msg)[4..12].try_into().unwrap()) }
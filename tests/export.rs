@@ -0,0 +1,94 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+
+#[test]
+fn dump_jsonl_locates_each_message() {
+    let first = [depth(1, 10_000_000_000, 5, MarketFlag::BUY), symbol(2, b"A\"B")].concat();
+    let second = [tick(3, 7, 200, 1, MarketFlag::SELL), vec![99, 0, 12, 0, 4, 0, 0, 0, 0, 0, 0, 0]].concat();
+    let second_pos = 4 + 4 + k4os_pickler::pickle(&first).unwrap().len();
+    let path = write_image("dump", 4096, &[first, second]);
+
+    let mut out = Vec::new();
+    assert_eq!(dump_jsonl(FastCacheReader::open(&path).unwrap(), &mut out).unwrap(), 4);
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines[..3], [
+        r#"{"seq":0,"block_pos":4,"offset":0,"kind":"Depth","time":1,"price":10000000000,"volume":5,"flags":1}"#.to_string(),
+        r#"{"seq":1,"block_pos":4,"offset":29,"kind":"Symbol","time":2,"name":"A\"B"}"#.to_string(),
+        format!(r#"{{"seq":2,"block_pos":{second_pos},"offset":0,"kind":"Tick","time":3,"id":7,"price":200,"volume":1,"side":2}}"#),
+    ]);
+    assert_eq!(lines[3], format!(
+        r#"{{"seq":3,"block_pos":{second_pos},"offset":37,"kind":"Invalid","raw_kind":99,"size":12,"error":"unknown message kind 99"}}"#
+    ));
+}
+
+#[test]
+fn decompress_to_writes_the_raw_message_stream() {
+    let blocks = [depths(&[1, 2]), [tick(3, 1, 2, 3, MarketFlag::BUY), symbol(4, b"XYZ")].concat(), depths(&[5])];
+    let src = write_image("unpack-src", 4096, &blocks);
+    let dst = tmp("unpack-dst");
+    let written = decompress_to(&src, &dst).unwrap();
+
+    let raw = std::fs::read(&dst).unwrap();
+    assert_eq!(written as usize, raw.len());
+    assert_eq!(raw.len(), blocks.iter().map(Vec::len).sum::<usize>());
+    assert_eq!(raw, blocks.concat());
+
+    // With no framing left, the stream walks as one long block.
+    let times: Vec<_> = BlockMessages::new(&raw).map(|m| m.unwrap().header().time).collect();
+    assert_eq!(times, [1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn split_by_symbol_keeps_each_subset_in_order() {
+    let (esz4, nq) = (symbol(10, b"ESZ4"), symbol(20, b"NQ/H5"));
+    let d = |t| depths(&[t]);
+    let src = write_image("split-src", 256, &[
+        [depths(&[1]), esz4.clone(), depths(&[11, 12])].concat(),       // leading depth has no symbol
+        [nq.clone(), tick(21, 1, 2, 3, MarketFlag::BUY), depths(&[22])].concat(),
+        [esz4.clone(), depths(&[13]), nq.clone(), depths(&[23])].concat(),
+    ]);
+    let dir = tmp("split-out");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let counts = split_by_symbol(&src, &dir).unwrap();
+    assert_eq!(counts.into_iter().collect::<Vec<_>>(), [("ESZ4".to_string(), 5), ("NQ/H5".to_string(), 5)]);
+
+    let read = |name: &str| {
+        let rdr = FastCacheReader::open(&format!("{dir}/{name}.bin.lz4")).unwrap();
+        assert_eq!(rdr.buffer_len(), 256);
+        drain(rdr)
+    };
+    assert_eq!(read("ESZ4"), [esz4.clone(), d(11), d(12), esz4, d(13)]);
+    assert_eq!(read("NQ_H5"), [nq.clone(), tick(21, 1, 2, 3, MarketFlag::BUY), d(22), nq, d(23)]);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+}
+
+#[test]
+fn stream_digest_ignores_framing_but_not_messages() {
+    let msgs = [depths(&[1, 2, 3]), tick(4, 1, 2, 3, MarketFlag::SELL), symbol(5, b"ESZ4"), depths(&[6])].concat();
+    let digest = |img: Vec<u8>| stream_digest(FastCacheReader::from_bytes(img).unwrap()).unwrap();
+
+    // FNV‑1a of the concatenated message bytes, so the empty stream is the
+    // offset basis.
+    let fnv = msgs.iter().fold(0xcbf2_9ce4_8422_2325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x100_0000_01b3));
+    assert_eq!(digest(image(4096, std::slice::from_ref(&msgs))), fnv);
+    assert_eq!(digest(image(4096, &[])), 0xcbf2_9ce4_8422_2325);
+
+    // Re‑blocked, stored instead of compressed, or transcoded: the same.
+    let (a, b) = msgs.split_at(58);
+    assert_eq!(digest(image(64, &[a.to_vec(), b.to_vec()])), fnv);
+    let mut stored = 4096i32.to_le_bytes().to_vec();
+    push_frame(&mut stored, &k4os_pickler::pickle_with(&msgs, CompressionMode::Stored).unwrap());
+    assert_eq!(digest(stored), fnv);
+    let (src, dst) = (write_image("digest-src", 64, &[a.to_vec(), b.to_vec()]), tmp("digest-dst"));
+    transcode(&src, &dst, 1 << 16).unwrap();
+    assert_eq!(stream_digest(FastCacheReader::open(&dst).unwrap()).unwrap(), fnv);
+
+    // One byte of one message changed: different.
+    let mut changed = msgs.clone();
+    changed[20] ^= 1;
+    assert_ne!(digest(image(4096, &[changed])), fnv);
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+use std::{ffi::c_void, ptr};
+
+#[test]
+fn non_utf8_path_is_refused_not_mangled() {
+    let mut out = ptr::null_mut::<c_void>();
+    let path = c"\xff-faststorage-no-such-file.bin";
+    assert_eq!(unsafe { open_reader(path.as_ptr(), &mut out) }, ERR_INVALID_ARGS);
+    assert!(out.is_null());
+
+    // A lossy conversion would have created "\u{FFFD}-….bin" instead.
+    let dir = std::env::temp_dir().join(format!("faststorage-test-{}-ffi", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut raw = dir.to_str().unwrap().as_bytes().to_vec();
+    raw.extend_from_slice(b"/\xffout.bin\0");
+    let path = std::ffi::CStr::from_bytes_with_nul(&raw).unwrap();
+    assert_eq!(unsafe { open_writer(path.as_ptr(), 4096, 1, &mut out) }, ERR_INVALID_ARGS);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[test]
+fn invalid_utf8_symbol_name() {
+    let path = write_image("bad-symbol", 4096, &[[symbol(1, b"A\xffB"), depths(&[2])].concat()]);
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    let err = rdr.next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::CorruptMessage(m) if m.contains("UTF-8")), "{err}");
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_symbol_decoding(SymbolDecoding::Lossy);
+    match rdr.next_typed().unwrap() {
+        Some(Message::Symbol(s)) => assert_eq!(s.name, "A\u{FFFD}B"),
+        m => panic!("{m:?}"),
+    }
+    assert!(matches!(rdr.next_typed().unwrap(), Some(Message::Depth(_))));
+}
+
+/// Opens `path` through the C ABI.
+fn c_open(path: &str) -> *mut c_void {
+    let path = std::ffi::CString::new(path).unwrap();
+    let mut h = ptr::null_mut();
+    assert_eq!(unsafe { open_reader(path.as_ptr(), &mut h) }, 0);
+    h
+}
+
+#[test]
+fn last_header_accessors_match_the_message() {
+    let path = write_image("last-accessors", 4096, &[[depths(&[5]), tick(6, 1, 2, 3, MarketFlag::SELL)].concat(), depths(&[7]), depths(&[-1])]);
+    let h = c_open(&path);
+    unsafe {
+        assert_eq!((last_kind(h), last_time(h), last_size(h)), (-1, -1, -1));
+        let mut msg = ptr::null();
+        let mut n = 0;
+        loop {
+            let size = read_message(h, &mut msg);
+            if size == 0 { break; }
+            let hdr = ptr::read_unaligned(msg as *const MessageHeader);
+            assert_eq!((last_kind(h), last_time(h), last_size(h)), (hdr.kind, hdr.time, size));
+            assert_eq!(last_size(h), hdr.size as i32);
+            n += 1;
+        }
+        assert_eq!(n, 4);
+        // Time -1 is a real timestamp here; last_kind tells it from "nothing read".
+        assert_eq!(last_time(h), -1);
+        assert_eq!(last_kind(h), MessageKind::Depth as i16);
+        close_reader(h);
+    }
+}
+
+#[test]
+fn c_writer_output_reads_back() {
+    let path = tmp("c-writer");
+    let c = std::ffi::CString::new(path.as_str()).unwrap();
+    let msgs: Vec<_> = (0..40).map(|t| depth(t, 100 + t, 1, MarketFlag::SELL)).collect();
+    let mut h = ptr::null_mut();
+    unsafe {
+        assert_eq!(open_writer(c.as_ptr(), 256, 1, &mut h), 0);
+        for m in &msgs { assert_eq!(write_message(h, m.as_ptr().cast(), m.len() as i32), 0); }
+        assert_eq!(close_writer(h), 0);
+    }
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(bytes[..4], 256i32.to_le_bytes());
+    assert_eq!(drain(FastCacheReader::open(&path).unwrap()), msgs);
+
+    // Forty 29‑byte messages in 256‑byte blocks: eight to a block.
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    while rdr.next_typed().unwrap().is_some() {}
+    assert_eq!(rdr.compression_stats().blocks, 5);
+}
+
+#[test]
+fn compression_stats_through_the_c_abi() {
+    let blocks = [depths(&[1; 20]), depths(&[2])];
+    let path = write_image("c-stats", 4096, &blocks);
+    let h = c_open(&path);
+    let (mut blocks_read, mut compressed, mut decompressed) = (9, 9, 9);
+    unsafe {
+        assert_eq!(get_compression_stats(h, &mut blocks_read, &mut compressed, &mut decompressed), 0);
+        assert_eq!((blocks_read, compressed, decompressed), (0, 0, 0));
+        let mut msg = ptr::null();
+        while read_message(h, &mut msg) > 0 {}
+        assert_eq!(get_compression_stats(h, &mut blocks_read, &mut compressed, &mut decompressed), 0);
+        assert_eq!(get_compression_stats(h, ptr::null_mut(), &mut compressed, &mut decompressed), -1);
+        close_reader(h);
+    }
+    let pickled: u64 = blocks.iter().map(|b| k4os_pickler::pickle(b).unwrap().len() as u64).sum();
+    assert_eq!((blocks_read, compressed, decompressed), (2, pickled, 21 * 29));
+}
+
+#[test]
+fn empty_and_header_only_files() {
+    let empty = tmp("empty");
+    std::fs::write(&empty, b"").unwrap();
+    assert!(matches!(FastCacheReader::open(&empty).err().map(|e| e.root().code()), Some(ERR_EMPTY_FILE)));
+    assert!(matches!(FastCacheReader::from_bytes(Vec::new()).err().map(|e| e.root().code()), Some(ERR_EMPTY_FILE)));
+    let c = std::ffi::CString::new(empty).unwrap();
+    let mut h = ptr::null_mut();
+    assert_eq!(unsafe { open_reader(c.as_ptr(), &mut h) }, ERR_EMPTY_FILE);
+    assert!(h.is_null());
+
+    let header_only = write_image("header-only", 4096, &[]);
+    let h = c_open(&header_only);
+    unsafe {
+        let mut msg = ptr::null();
+        assert_eq!(read_message(h, &mut msg), 0);
+        assert_eq!(read_message(h, &mut msg), 0);
+        close_reader(h);
+    }
+    let rdr = FastCacheReader::open(&header_only).unwrap();
+    assert_eq!(rdr.buffer_len(), 4096);
+    assert!(drain(rdr).is_empty());
+}
+
+#[test]
+fn reset_reader_replays_through_the_c_abi() {
+    let path = write_image("c-reset", 4096, &[depths(&[1, 2]), [tick(3, 1, 2, 3, MarketFlag::BUY), depths(&[4])].concat()]);
+    let h = c_open(&path);
+    let pass = |stop: usize| unsafe {
+        let (mut msg, mut out) = (ptr::null(), Vec::new());
+        while out.len() < stop && read_message(h, &mut msg) > 0 {
+            out.push(std::slice::from_raw_parts(msg as *const u8, last_size(h) as usize).to_vec());
+        }
+        out
+    };
+    let first = pass(usize::MAX);
+    assert_eq!(first.len(), 4);
+    unsafe { assert_eq!(reset_reader(h), 0); }
+    assert_eq!(pass(usize::MAX), first);
+
+    // Mid‑block, and with the kind filter kept across the reset.
+    unsafe { assert_eq!(reset_reader(h), 0); }
+    assert_eq!(pass(1), first[..1]);
+    let tick_kind = [MessageKind::Tick as i16];
+    unsafe {
+        assert_eq!(set_kind_filter(h, tick_kind.as_ptr(), 1), 0);
+        assert_eq!(reset_reader(h), 0);
+    }
+    assert_eq!(pass(usize::MAX), first[2..3]);
+    unsafe {
+        assert_eq!(last_time(h), 3);
+        close_reader(h);
+        assert_eq!(reset_reader(ptr::null_mut()), -1);
+    }
+}
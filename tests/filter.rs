@@ -0,0 +1,97 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+
+fn tk(time: i64) -> Vec<u8> { tick(time, time, 100, 1, MarketFlag::BUY) }
+
+#[test]
+fn kind_filter_passes_only_ticks() {
+    // A block of depths alone, and a terminator that ends the stream
+    // with a tick still behind it in the block and another after it.
+    let path = write_image("kind-filter", 4096, &[
+        [depths(&[1]), tk(2), depths(&[3])].concat(),
+        depths(&[4, 5]),
+        [tk(6), vec![0; 12], tk(7)].concat(),
+        tk(8),
+    ]);
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_kind_filter(&[MessageKind::Tick]);
+    assert_eq!(times(rdr), [2, 6]);
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_kind_filter(&[MessageKind::Tick]);
+    let mut kinds = Vec::new();
+    while let Some(m) = rdr.next_typed().unwrap() { kinds.push(matches!(m, Message::Tick(_))); }
+    assert_eq!(kinds, [true, true]);
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_kind_filter(&[MessageKind::Tick, MessageKind::Depth]);
+    assert_eq!(times(rdr), [1, 2, 3, 4, 5, 6]);
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_kind_filter(&[MessageKind::Candle]);
+    assert_eq!(times(rdr), []);
+}
+
+#[test]
+fn time_range_bounds_inside_blocks() {
+    let path = write_image("time-range", 4096, &[depths(&[1, 2, 3, 4]), depths(&[5, 6, 7, 8]), depths(&[9, 10])]);
+    let range = |r: std::ops::Range<i64>| {
+        let mut rdr = FastCacheReader::open(&path).unwrap();
+        rdr.set_time_range(r);
+        times(rdr)
+    };
+    assert_eq!(range(3..7), [3, 4, 5, 6]);
+    assert_eq!(range(2..3), [2]);
+    assert_eq!(range(6..100), [6, 7, 8, 9, 10]);
+    assert_eq!(range(5..5), []);
+    assert_eq!(range(11..20), []);
+
+    // The stop message is left in place: widening the range goes on from it.
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_time_range(3..6);
+    assert!(rdr.seek_to_time(3).unwrap());
+    let mut got = Vec::new();
+    while let Some(m) = rdr.next_typed().unwrap() { got.push(time_of(&m)); }
+    assert_eq!(got, [3, 4, 5]);
+    rdr.set_time_range(0..8);
+    while let Some(m) = rdr.next_typed().unwrap() { got.push(time_of(&m)); }
+    assert_eq!(got, [3, 4, 5, 6, 7]);
+
+    // With a kind filter as well.
+    let path = write_image("time-range-kinds", 4096, &[[depths(&[1]), tk(2), depths(&[3]), tk(4)].concat(), [tk(5), depths(&[6])].concat()]);
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_kind_filter(&[MessageKind::Tick]);
+    rdr.set_time_range(3..6);
+    assert_eq!(times(rdr), [4, 5]);
+}
+
+#[test]
+fn pages_of_100_concatenate_to_a_full_read() {
+    let blocks: Vec<_> = (0..7i64).map(|b| (b * 90..b * 90 + 90).flat_map(|t| if t % 4 == 0 { tk(t) } else { depths(&[t]) }).collect()).collect();
+    let path = write_image("pages", 8192, &blocks);
+    let pages = |setup: &dyn Fn(&mut FastCacheReader)| {
+        let mut full = FastCacheReader::open(&path).unwrap();
+        setup(&mut full);
+        let mut want = Vec::new();
+        while let Some(m) = full.next_typed().unwrap() { want.push(encode(&m)); }
+
+        let mut rdr = FastCacheReader::open(&path).unwrap();
+        setup(&mut rdr);
+        let mut got = Vec::new();
+        loop {
+            let page = rdr.read_up_to(100).unwrap();
+            assert!(page.len() <= 100);
+            got.extend(page.iter().map(encode));
+            if page.len() < 100 { break; }
+        }
+        assert!(rdr.read_up_to(100).unwrap().is_empty());
+        assert_eq!(got, want);
+        want.len()
+    };
+    assert_eq!(pages(&|_| {}), 630);
+    assert_eq!(pages(&|r| r.set_kind_filter(&[MessageKind::Tick])), 158);
+    assert_eq!(pages(&|r| r.set_time_range(150..420)), 270);
+}
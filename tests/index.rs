@@ -0,0 +1,105 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+use std::fs::{self, File};
+
+fn three_blocks(name: &str) -> String {
+    write_image(name, 4096, &[depths(&[10, 20]), depths(&[30, 40]), [depths(&[50]), tick(60, 1, 2, 3, MarketFlag::BUY)].concat()])
+}
+
+#[test]
+fn seeks_with_index() {
+    let path = three_blocks("index-seek");
+    let mut rdr = FastCacheReader::open_with_index(&path).unwrap();
+    assert!(fs::metadata(BlockIndex::sidecar_path(&path)).is_ok(), "sidecar written on first open");
+    assert!(rdr.seek_to_time(35).unwrap());
+    assert_eq!(times(rdr), [40, 50, 60]);
+
+    let index = BlockIndex::load(&BlockIndex::sidecar_path(&path)).unwrap();
+    let firsts: Vec<_> = index.entries().iter().map(|e| e.first_time).collect();
+    assert_eq!(firsts, [10, 30, 50]);
+}
+
+#[test]
+fn index_records_metadata() {
+    let path = three_blocks("index-meta");
+    let index = BlockIndex::build(&path).unwrap();
+    let scanned = scan_metadata(&path).unwrap();
+    assert_eq!(index.metadata(), Some(&scanned));
+    assert_eq!((scanned.blocks, scanned.messages, scanned.count(MessageKind::Tick)), (3, 6, 1));
+    assert_eq!((scanned.min_time, scanned.max_time), (Some(10), Some(60)));
+
+    index.save(&BlockIndex::sidecar_path(&path)).unwrap();
+    let loaded = BlockIndex::load(&BlockIndex::sidecar_path(&path)).unwrap();
+    assert_eq!(loaded.metadata(), Some(&scanned));
+    assert_eq!(loaded.entries(), index.entries());
+}
+
+#[test]
+fn reuses_sidecar_only_while_length_and_mtime_match() {
+    let path = three_blocks("index-reuse");
+    let sidecar = BlockIndex::sidecar_path(&path);
+    BlockIndex::build(&path).unwrap().save(&sidecar).unwrap();
+    let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+
+    // Same length and mtime: the sidecar is trusted and the file not read,
+    // so even garbage of the right length goes unnoticed.
+    let len = fs::metadata(&path).unwrap().len() as usize;
+    let mut junk = 4096i32.to_le_bytes().to_vec();
+    junk.resize(len, 0xff);
+    fs::write(&path, &junk).unwrap();
+    File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+    assert_eq!(scan_metadata(&path).unwrap().messages, 6);
+    assert_eq!(BlockIndex::load_or_build(&path).unwrap().entries().len(), 3);
+
+    // A new mtime makes it stale, so the file is scanned again.
+    File::options().write(true).open(&path).unwrap().set_modified(mtime + std::time::Duration::from_secs(1)).unwrap();
+    assert!(!BlockIndex::load(&sidecar).unwrap().is_current(&path).unwrap());
+    assert!(scan_metadata(&path).is_err());
+    assert!(BlockIndex::load_or_build(&path).is_err());
+
+    // A valid file of another length rebuilds and replaces the sidecar.
+    fs::write(&path, image(4096, &[depths(&[1, 2, 3])])).unwrap();
+    let index = BlockIndex::load_or_build(&path).unwrap();
+    assert_eq!(index.metadata().unwrap().messages, 3);
+    assert!(BlockIndex::load(&sidecar).unwrap().is_current(&path).unwrap());
+}
+
+#[test]
+fn loads_version_1_sidecar() {
+    let path = tmp("index-v1.idx");
+    let mut v1 = b"FSIX".to_vec();
+    v1.extend_from_slice(&1u32.to_le_bytes());
+    v1.extend_from_slice(&123u64.to_le_bytes());
+    v1.extend_from_slice(&1u64.to_le_bytes());
+    v1.extend_from_slice(&7i64.to_le_bytes());
+    v1.extend_from_slice(&4u64.to_le_bytes());
+    v1.extend_from_slice(&99u32.to_le_bytes());
+    fs::write(&path, &v1).unwrap();
+    let index = BlockIndex::load(&path).unwrap();
+    assert_eq!(index.entries(), [BlockIndexEntry { first_time: 7, file_pos: 4, block_len: 99 }]);
+    assert_eq!(index.metadata(), None);
+}
+
+#[test]
+fn index_offsets_land_on_frame_starts() {
+    let blocks: Vec<_> = (0..6i64).map(|b| depths(&(b * 10..b * 10 + 1 + b * 3).collect::<Vec<_>>())).collect();
+    let path = write_image("index-offsets", 4096, &blocks);
+    let bytes = fs::read(&path).unwrap();
+    let index = BlockIndex::build(&path).unwrap();
+    assert_eq!(index.entries().len(), 6);
+
+    let mut pos = 4;
+    for (e, raw) in index.entries().iter().zip(&blocks) {
+        assert_eq!(e.file_pos, pos);
+        let at = pos as usize;
+        let len = i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        assert_eq!(len as u32, e.block_len);
+        let block = k4os_pickler::unpickle(&bytes[at + 4..at + 4 + len as usize]).unwrap();
+        assert_eq!(&block, raw);
+        assert_eq!(i64::from_le_bytes(block[4..12].try_into().unwrap()), e.first_time);
+        pos += 4 + len as u64;
+    }
+    assert_eq!(pos, bytes.len() as u64);
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use common::*;
+use faststorage_native::orderbook::*;
+use faststorage_native::*;
+
+const E8: i64 = 100_000_000;
+
+fn bid(price: i64, flags: MarketFlag) -> DepthItem { DepthItem::new(price, price * E8, E8, (MarketFlag::BUY | flags).bits()) }
+fn ask(price: i64, flags: MarketFlag) -> DepthItem { DepthItem::new(price, price * E8, E8, flags.bits()) }
+fn pull_ask(price: i64) -> DepthItem { DepthItem::new(price, price * E8, 0, 0) }
+
+#[test]
+fn detects_crossed_and_locked() {
+    let mut book = DepthBook::default();
+    for item in [bid(100, MarketFlag::empty()), ask(101, MarketFlag::empty())] { book.apply(&item); }
+    assert!(!book.is_crossed() && !book.is_locked());
+    book.apply(&bid(101, MarketFlag::empty()));
+    assert!(book.is_locked() && !book.is_crossed());
+    book.apply(&bid(102, MarketFlag::empty()));
+    assert!(book.is_crossed() && !book.is_locked());
+}
+
+#[test]
+fn checks_every_update_outside_atomic_mode() {
+    let mut book = DepthBook::default();
+    assert_eq!(book.apply_checked(&bid(100, MarketFlag::empty())), Ok(false));
+    assert_eq!(book.apply_checked(&ask(101, MarketFlag::empty())), Ok(false));
+    // No END_OF_TX anywhere, yet the cross is caught on the update that made it.
+    assert_eq!(book.apply_checked(&bid(102, MarketFlag::empty())), Err(CrossedBook { time: 102, bid: 102.0, ask: 101.0 }));
+
+    let mut book = IntDepthBook::default();
+    book.apply_checked(&bid(100, MarketFlag::empty())).unwrap();
+    book.apply_checked(&ask(101, MarketFlag::empty())).unwrap();
+    assert_eq!(book.apply_checked(&bid(101, MarketFlag::empty())), Err(CrossedBook { time: 101, bid: 101 * E8, ask: 101 * E8 }));
+}
+
+#[test]
+fn atomic_mode_allows_crossing_inside_a_transaction() {
+    let mut book = DepthBook::default();
+    let mut int = IntDepthBook::default();
+    book.set_atomic(true);
+    int.set_atomic(true);
+    let ok = [
+        bid(100, MarketFlag::empty()), ask(101, MarketFlag::END_OF_TX),
+        bid(102, MarketFlag::empty()), pull_ask(101), ask(103, MarketFlag::END_OF_TX),
+    ];
+    for item in &ok {
+        book.apply_checked(item).unwrap();
+        int.apply_checked(item).unwrap();
+    }
+    assert_eq!((book.best_bid(), book.best_ask()), (Some((102.0, 1.0)), Some((103.0, 1.0))));
+
+    let bad = [bid(104, MarketFlag::empty()), ask(105, MarketFlag::END_OF_TX)];
+    assert_eq!(book.apply_checked(&bad[0]), Ok(false));
+    assert_eq!(book.apply_checked(&bad[1]), Err(CrossedBook { time: 105, bid: 104.0, ask: 103.0 }));
+    assert_eq!(int.apply_checked(&bad[0]), Ok(false));
+    assert_eq!(int.apply_checked(&bad[1]), Err(CrossedBook { time: 105, bid: 104 * E8, ask: 103 * E8 }));
+}
+
+#[test]
+fn timeline_interleaves_typed_rows_in_time_order() {
+    let level = |time, price: i64, volume: i64, side: MarketFlag| Message::Depth(DepthItem::new(time, price * E8, volume * E8, side.bits()));
+    let path = std::env::temp_dir().join(format!("faststorage-test-{}-timeline", std::process::id()));
+    let path = path.to_str().unwrap();
+    let mut w = FastCacheWriter::create(path, 4096, CompressionMode::Lz4).unwrap();
+    for m in [
+        level(1, 100, 1, MarketFlag::BUY),
+        level(2, 101, 1, MarketFlag::empty()),
+        Message::Tick(TickItem::new(3, 1, 100 * E8, 2 * E8, MarketFlag::BUY.bits())),
+        level(4, 100, 1, MarketFlag::BUY),          // first book row after the snapshot
+        level(5, 102, 1, MarketFlag::empty()),      // below the top: no row
+        level(6, 100, 3, MarketFlag::BUY),
+        Message::Tick(TickItem::new(7, 2, 101 * E8, E8, 0)),
+    ] { w.write_typed(&m).unwrap(); }
+    w.finish().unwrap();
+
+    let mut out = Vec::new();
+    let rows = export_timeline_csv(FastCacheReader::open(path).unwrap(), &mut out, &Scale::DEFAULT).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines[0], "time;type;price;volume;side;bestAskPrice;bestAskVolume;bestBidPrice;bestBidVolume");
+    assert_eq!(&lines[1..], [
+        "3;trade;100.00000000;2.00000000;buy;;;;",
+        "4;book;;;;101.00000000;1.00000000;100.00000000;1.00000000",
+        "6;book;;;;101.00000000;1.00000000;100.00000000;3.00000000",
+        "7;trade;101.00000000;1.00000000;;;;;",
+    ]);
+    assert_eq!(rows, 4);
+}
+
+#[test]
+fn timeline_keeps_feed_order_when_the_clock_steps_back() {
+    let level = |time, price: i64, volume: i64, side: MarketFlag| Message::Depth(DepthItem::new(time, price * 100, volume * 100, side.bits()));
+    let path = tmp("timeline-regression");
+    let mut w = FastCacheWriter::create(&path, 4096, CompressionMode::Lz4).unwrap();
+    for m in [
+        level(10, 100, 1, MarketFlag::BUY),
+        level(11, 101, 1, MarketFlag::empty()),
+        Message::Tick(TickItem::new(20, 1, 100 * 100, 200, MarketFlag::BUY.bits())),
+        level(15, 101, 3, MarketFlag::empty()),     // clock steps back
+        Message::Tick(TickItem::new(12, 2, 99 * 100, 100, 0)),
+    ] { w.write_typed(&m).unwrap(); }
+    w.finish().unwrap();
+
+    let mut out = Vec::new();
+    let scale = Scale { price_divisor: 100.0, volume_divisor: 100.0 };
+    let rows = export_timeline_csv(FastCacheReader::open(&path).unwrap(), &mut out, &scale).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(&out.lines().skip(1).collect::<Vec<_>>(), &[
+        "20;trade;100.00000000;2.00000000;buy;;;;",
+        "15;book;;;;101.00000000;3.00000000;100.00000000;1.00000000",
+        "12;trade;99.00000000;1.00000000;;;;;",
+    ]);
+    assert_eq!(rows, 3);
+}
+
+#[test]
+fn snapshot_ladder_after_clear() {
+    let lvl = |price: i64, volume: i64, flags: MarketFlag| DepthItem::new(0, price * E8, volume * E8, flags.bits());
+    let mut book = DepthBook::default();
+    for item in [
+        lvl(99, 1, MarketFlag::BUY), lvl(98, 2, MarketFlag::BUY), lvl(105, 1, MarketFlag::empty()),
+        // Wipes all of the above, then sets this bid.
+        lvl(100, 5, MarketFlag::BUY | MarketFlag::CLEAR),
+        lvl(97, 3, MarketFlag::BUY), lvl(99, 4, MarketFlag::BUY), lvl(96, 1, MarketFlag::BUY),
+        lvl(102, 2, MarketFlag::empty()), lvl(101, 1, MarketFlag::empty()), lvl(103, 6, MarketFlag::empty()),
+        lvl(97, 0, MarketFlag::BUY), lvl(103, 0, MarketFlag::empty()),
+    ] { book.apply(&item); }
+
+    assert_eq!(book.snapshot(3), BookSnapshot {
+        bids:   vec![(100.0, 5.0), (99.0, 4.0), (96.0, 1.0)],
+        asks:   vec![(101.0, 1.0), (102.0, 2.0)],
+        mid:    Some(100.5),
+        spread: Some(1.0),
+    });
+    assert_eq!(book.snapshot(1).bids, [(100.0, 5.0)]);
+    assert_eq!(book.snapshot(0), BookSnapshot { mid: Some(100.5), spread: Some(1.0), ..Default::default() });
+
+    book.apply(&lvl(100, 1, MarketFlag::BUY | MarketFlag::CLEAR));
+    assert_eq!(book.snapshot(5), BookSnapshot { bids: vec![(100.0, 1.0)], ..Default::default() });
+}
+
+#[test]
+fn atomic_mode_hides_a_crossed_intermediate_state() {
+    // The bid moves up through the old ask before that ask is pulled.
+    let tx = [bid(102, MarketFlag::empty()), pull_ask(101), ask(103, MarketFlag::END_OF_TX)];
+    let mut plain = DepthBook::default();
+    let mut atomic = DepthBook::default();
+    atomic.set_atomic(true);
+    for book in [&mut plain, &mut atomic] {
+        book.apply(&bid(100, MarketFlag::empty()));
+        assert!(book.apply(&ask(101, MarketFlag::END_OF_TX)));
+    }
+
+    assert!(!plain.apply(&tx[0]));
+    assert!(plain.is_crossed(), "applied one by one, the book crosses");
+    assert!(!atomic.apply(&tx[0]));
+    assert!(!atomic.apply(&tx[1]));
+    assert_eq!(atomic.pending(), 2);
+    assert!(!atomic.is_crossed());
+    assert_eq!((atomic.best_bid(), atomic.best_ask()), (Some((100.0, 1.0)), Some((101.0, 1.0))));
+
+    assert!(atomic.apply(&tx[2]), "END_OF_TX signals a consistent book");
+    assert_eq!(atomic.pending(), 0);
+    assert!(!atomic.is_crossed() && !atomic.is_locked());
+    assert_eq!((atomic.best_bid(), atomic.best_ask()), (Some((102.0, 1.0)), Some((103.0, 1.0))));
+}
+
+#[test]
+fn checkpoint_seek_matches_a_replay_from_the_start() {
+    // 30 blocks of 25 updates on both sides, a few removals and a CLEAR
+    // now and then.
+    let mut x = 88172645463325252u64;
+    let path = &tmp("checkpoints");
+    let mut w = FastCacheWriter::create(path, 25 * 29, CompressionMode::Lz4).unwrap();
+    for t in 0..750i64 {
+        x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+        let (price, volume) = (90 + (x % 20) as i64, (x >> 8) as i64 % 4);
+        let mut flags = if price < 100 { MarketFlag::BUY } else { MarketFlag::empty() };
+        if t % 170 == 169 { flags |= MarketFlag::CLEAR; }
+        w.write_typed(&Message::Depth(DepthItem::new(t, price * E8, volume * E8, flags.bits()))).unwrap();
+    }
+    w.finish().unwrap();
+
+    BookCheckpoints::build(path, 4).unwrap().save(&BookCheckpoints::sidecar_path(path)).unwrap();
+    let cps = BookCheckpoints::load_for(path).unwrap();
+    assert_eq!(cps.checkpoints().len(), 7);
+
+    for ts in [0, 1, 99, 100, 101, 333, 600, 749, 750, 5_000] {
+        let mut scratch = DepthBook::default();
+        let mut rdr = FastCacheReader::open(path).unwrap();
+        while rdr.peek().unwrap().is_some_and(|h| { h.time } < ts) {
+            let Some(Message::Depth(d)) = rdr.next_typed().unwrap() else { unreachable!() };
+            scratch.apply(&d);
+        }
+
+        let mut seeker = FastCacheReader::open(path).unwrap();
+        let book = cps.seek(&mut seeker, ts).unwrap();
+        assert_eq!(book.snapshot(usize::MAX), scratch.snapshot(usize::MAX), "ts {ts}");
+        assert_eq!(seeker.next_typed().unwrap().map(|m| encode(&m)), rdr.next_typed().unwrap().map(|m| encode(&m)), "ts {ts}");
+    }
+}
+
+#[test]
+fn counts_updates_that_leave_the_book_unchanged() {
+    let mut book = DepthBook::default();
+    let mut int = IntDepthBook::default();
+    for item in [
+        bid(100, MarketFlag::empty()),
+        bid(100, MarketFlag::empty()),                  // same volume again: redundant
+        DepthItem::new(0, 100 * E8, 2 * E8, MarketFlag::BUY.bits()),
+        pull_ask(105),                                  // removing a missing level: redundant
+        ask(101, MarketFlag::empty()),
+        pull_ask(101),
+        ask(102, MarketFlag::CLEAR),
+        ask(102, MarketFlag::CLEAR),                    // CLEAR never counts
+    ] {
+        book.apply(&item);
+        int.apply(&item);
+    }
+    assert_eq!(book.redundant_updates(), 2);
+    assert_eq!(int.redundant_updates(), 2);
+}
@@ -0,0 +1,30 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+use std::time::{Duration, Instant};
+
+#[test]
+fn paces_across_blocks_within_tolerance() {
+    let t0 = DOTNET_UNIX_EPOCH_TICKS;
+    let ms = 10_000;   // .NET ticks
+    let path = write_image("paced", 4096, &[depths(&[t0, t0 + 300 * ms]), depths(&[t0 + 600 * ms, t0 + 1000 * ms])]);
+    let mut rdr = PacedReader::new(FastCacheReader::open(&path).unwrap(), 10.0).unwrap();
+    let start = Instant::now();
+    let mut n = 0;
+    while rdr.next_message().unwrap().is_some() { n += 1; }
+    let elapsed = start.elapsed();
+    assert_eq!(n, 4);
+    // One recorded second at 10× is 100 ms; sleeps never end early. They
+    // may overrun by any amount on a loaded machine, so no upper bound.
+    assert!(elapsed >= Duration::from_millis(100), "{elapsed:?}");
+}
+
+#[test]
+fn rejects_bad_speed() {
+    for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+        let path = write_image("paced-speed", 4096, &[depths(&[1])]);
+        let err = PacedReader::new(FastCacheReader::open(&path).unwrap(), speed).err().unwrap();
+        assert!(matches!(err, FastStorageError::Invalid(_)), "{speed}: {err}");
+    }
+}
@@ -0,0 +1,427 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+
+#[test]
+fn strict_time_reports_backward_jump_across_blocks() {
+    let path = write_image("strict-time", 4096, &[depths(&[10, 20, 15]), depths(&[12, 30])]);
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_strict_time(true);
+    let mut seen = Vec::new();
+    let mut errors = Vec::new();
+    loop {
+        match rdr.next_typed() {
+            Ok(Some(Message::Depth(d))) => seen.push(d.header.time),
+            Ok(Some(m))                 => panic!("unexpected {m:?}"),
+            Ok(None)                    => break,
+            Err(e)                      => errors.push(*match e.root() { FastStorageError::OutOfOrder(o) => o, e => panic!("{e}") }),
+        }
+    }
+    assert_eq!(seen, [10, 20, 30]);
+    assert_eq!(errors, [
+        OutOfOrderTime { block: 0, previous: 20, time: 15 },
+        OutOfOrderTime { block: 1, previous: 15, time: 12 },
+    ]);
+
+    rdr.rewind().unwrap();
+    rdr.set_lenient(true);
+    let mut n = 0;
+    while rdr.next_typed().unwrap().is_some() { n += 1; }
+    assert_eq!((n, rdr.out_of_order_times()), (5, 2));
+}
+
+#[test]
+fn strict_time_is_off_by_default() {
+    let path = write_image("strict-time-off", 4096, &[depths(&[10, 5, 1])]);
+    assert_eq!(times(FastCacheReader::open(&path).unwrap()), [10, 5, 1]);
+}
+
+#[test]
+fn grows_buffer_for_block_larger_than_declared() {
+    let small = depths(&[1, 2]);
+    let large = depths(&(3..200).collect::<Vec<_>>());
+    let path = write_image("grow-block", small.len() as i32, &[small, large]);
+    assert_eq!(times(FastCacheReader::open(&path).unwrap()), (1..200).collect::<Vec<_>>());
+}
+
+#[test]
+fn rejects_block_above_max_len() {
+    let path = write_image("max-block", 64, &[depths(&[1]), depths(&(2..200).collect::<Vec<_>>())]);
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_max_block_len(4096);
+    assert!(rdr.next_typed().unwrap().is_some());
+    let err = rdr.next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::BlockTooLarge { limit: 4096, .. }), "{err}");
+}
+
+#[test]
+fn rejects_corrupt_frame_length_before_allocating() {
+    let mut img = 4096i32.to_le_bytes().to_vec();
+    img.extend_from_slice(&0x7fff_fff0i32.to_le_bytes());
+    img.extend_from_slice(&[0; 64]);
+
+    // From a file the declared length is also past the end of the input,
+    // but the size limit is checked first.
+    let path = tmp("corrupt-frame-len");
+    std::fs::write(&path, &img).unwrap();
+    let err = FastCacheReader::open(&path).unwrap().next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::Invalid(_)), "{err}");
+
+    // A stream has no known length, so only the limit protects it.
+    let err = FastCacheReader::from_reader(std::io::Cursor::new(img)).unwrap().next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::Invalid(_)), "{err}");
+}
+
+#[test]
+fn frame_length_past_end_of_file_is_truncation() {
+    let mut img = 4096i32.to_le_bytes().to_vec();
+    img.extend_from_slice(&(1i32 << 20).to_le_bytes());
+    img.extend_from_slice(&[0; 64]);
+    let path = tmp("frame-past-eof");
+    std::fs::write(&path, &img).unwrap();
+    let err = FastCacheReader::open(&path).unwrap().next_typed().unwrap_err();
+    match err.root() {
+        FastStorageError::Truncated(t) => assert_eq!((t.block_pos, t.missing), (4, (1 << 20) - 64)),
+        e => panic!("{e}"),
+    }
+}
+
+#[test]
+fn rejects_header_claiming_enormous_buffer() {
+    let path = tmp("huge-header");
+    std::fs::write(&path, 0x7fff_fff0i32.to_le_bytes()).unwrap();
+    // Checked at the first read, so `set_max_block_len` can still raise it.
+    let err = FastCacheReader::open(&path).unwrap().next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::BlockTooLarge { len: 0x7fff_fff0, limit: DEFAULT_MAX_BLOCK_LEN }), "{err}");
+}
+
+#[test]
+fn message_locations_increase_and_start_blocks() {
+    let blocks = [depths(&[1, 2, 3]), depths(&[4]), [tick(5, 1, 2, 3, MarketFlag::BUY), depths(&[6])].concat()];
+    let path = write_image("locations", 4096, &blocks);
+    let img = std::fs::read(&path).unwrap();
+    let mut starts = vec![4u64];
+    for b in &blocks[..2] { starts.push(starts.last().unwrap() + 4 + k4os_pickler::pickle(b).unwrap().len() as u64); }
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    assert_eq!(rdr.current_message_location(), (0, 0));
+    let mut seen = Vec::new();
+    while let Some(m) = rdr.next_typed().unwrap() {
+        let loc = rdr.current_message_location();
+        assert!(seen.last().is_none_or(|&prev| loc > prev), "{loc:?} after {:?}", seen.last());
+        assert!(starts.contains(&loc.0), "{loc:?} is not a block start");
+        // The prefix there frames a block that decodes to hold this message.
+        let at = loc.0 as usize;
+        let len = i32::from_le_bytes(img[at..at + 4].try_into().unwrap()) as usize;
+        let block = k4os_pickler::unpickle(&img[at + 4..at + 4 + len]).unwrap();
+        assert_eq!(block[loc.1..loc.1 + encode(&m).len()], encode(&m)[..]);
+        seen.push(loc);
+    }
+    assert_eq!(seen.iter().map(|l| l.0).collect::<Vec<_>>(), [starts[0], starts[0], starts[0], starts[1], starts[2], starts[2]]);
+}
+
+#[test]
+fn lenient_mode_skips_a_corrupt_block_and_records_it() {
+    let path = tmp("lenient");
+    std::fs::write(&path, image_with_corrupt(4096, &[Some(depths(&[1, 2])), None, Some(depths(&[3]))])).unwrap();
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_lenient(true);
+    let mut seen = Vec::new();
+    while let Some(m) = rdr.next_typed().unwrap() { seen.push(time_of(&m)); }
+    assert_eq!(seen, [1, 2, 3]);
+    assert_eq!(rdr.skipped_blocks(), 1);
+    assert_eq!(rdr.errors().len(), 1);
+    assert_eq!(rdr.errors()[0].code(), ERR_DECOMPRESS, "{}", rdr.errors()[0]);
+
+    let mut strict = FastCacheReader::open(&path).unwrap();
+    assert_eq!(times_until_error(&mut strict), [1, 2]);
+}
+
+#[test]
+fn truncated_and_scribbled_blocks_never_read_out_of_bounds() {
+    let block = [depths(&[1, 2]), tick(3, 1, 2, 3, MarketFlag::BUY), symbol(4, b"ESZ4"), depths(&[5])].concat();
+    let ends = [29, 58, 95, 111, 140];
+
+    // Every cut: the whole messages before it, then either a clean end (a
+    // stub shorter than a header) or the partial message's offset.
+    for n in 1..=block.len() {
+        let mut msgs = FastCacheReader::from_bytes(image(4096, &[block[..n].to_vec()])).unwrap().messages();
+        let whole = ends.iter().take_while(|&&e| e <= n).count();
+        let start = if whole == 0 { 0 } else { ends[whole - 1] };
+        for _ in 0..whole { msgs.next_message().unwrap().unwrap(); }
+        match msgs.next_message() {
+            Ok(None) => assert!(n - start < 12, "cut at {n}"),
+            Err(e) => match e.root() {
+                FastStorageError::PartialMessage(p) => assert_eq!((p.offset, p.available), (start, n - start), "cut at {n}"),
+                e => panic!("cut at {n}: {e}"),
+            },
+            Ok(Some(_)) => panic!("cut at {n}: message past the cut"),
+        }
+    }
+
+    // Random bytes overwritten: whatever comes back lies inside the block
+    // and is as long as its header says.
+    let mut x = 0x9e37_79b9_7f4a_7c15u64;
+    for _ in 0..2000 {
+        let mut b = block.clone();
+        for _ in 0..3 {
+            x ^= x << 13; x ^= x >> 7; x ^= x << 17;
+            b[(x % block.len() as u64) as usize] = (x >> 32) as u8;
+        }
+        let n = 12 + (x >> 40) as usize % (block.len() - 11);
+        let mut msgs = FastCacheReader::from_bytes(image(4096, &[b[..n].to_vec()])).unwrap().messages();
+        let mut total = 0;
+        while let Ok(Some(m)) = msgs.next_message() {
+            assert_eq!(m.bytes().len(), u16::from_le_bytes([m.bytes()[2], m.bytes()[3]]) as usize);
+            total += m.bytes().len();
+        }
+        assert!(total <= n);
+    }
+}
+
+fn read_all(rdr: &mut FastCacheReader) -> Vec<Vec<u8>> {
+    let mut out = Vec::new();
+    while let Some(m) = rdr.next_typed().unwrap() { out.push(encode(&m)); }
+    out
+}
+
+#[test]
+fn rewind_replays_the_same_messages() {
+    let blocks = [depths(&[1, 2, 3]), tick(4, 1, 2, 3, MarketFlag::BUY), depths(&[5, 6])];
+    let path = write_image("rewind", 4096, &blocks);
+    let headerless = tmp("rewind-headerless");
+    std::fs::write(&headerless, &image(4096, &blocks)[4..]).unwrap();
+
+    let mut readers = vec![
+        FastCacheReader::open(&path).unwrap(),
+        FastCacheReader::open_headerless(&headerless, 4096).unwrap(),
+        FastCacheReader::from_bytes(image(4096, &blocks)).unwrap(),
+    ];
+    #[cfg(all(unix, target_pointer_width = "64"))]
+    readers.push(unsafe { FastCacheReader::open_mmap(&path) }.unwrap());   // nothing else touches the file
+
+    for rdr in &mut readers {
+        let first = read_all(rdr);
+        assert_eq!(first.len(), 6);
+        let stats = rdr.compression_stats();
+        rdr.rewind().unwrap();
+        assert_eq!(read_all(rdr), first);
+        assert_eq!(rdr.compression_stats(), stats);
+
+        // Mid‑block, the rest of the block is dropped.
+        rdr.rewind().unwrap();
+        rdr.next_typed().unwrap();
+        rdr.rewind().unwrap();
+        assert_eq!(read_all(rdr), first);
+    }
+}
+
+#[test]
+fn peek_then_next_neither_skips_nor_repeats() {
+    let blocks = [depths(&[1, 2]), depths(&[3]), [tick(4, 1, 2, 3, MarketFlag::BUY), depths(&[5])].concat()];
+    let path = write_image("peek", 4096, &blocks);
+
+    // Peeking every other message, sometimes twice, across block loads.
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    let mut got = Vec::new();
+    for i in 0.. {
+        if i % 2 == 0 {
+            let Some(h) = rdr.peek().unwrap().copied() else { break };
+            if i % 4 == 0 { assert_eq!(rdr.peek().unwrap().map(|h| h.time), Some(h.time)); }
+            let m = rdr.next_typed().unwrap().unwrap();
+            let MessageHeader { kind, time, .. } = h;
+            assert_eq!(time_of(&m), time);
+            assert_eq!(encode(&m)[..2], kind.to_le_bytes());
+            got.push(time);
+        } else {
+            let Some(m) = rdr.next_typed().unwrap() else { break };
+            got.push(time_of(&m));
+        }
+    }
+    assert_eq!(got, [1, 2, 3, 4, 5]);
+    assert!(rdr.peek().unwrap().is_none());
+
+    // Peek honours the kind filter.
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_kind_filter(&[MessageKind::Tick]);
+    assert_eq!(rdr.peek().unwrap().map(|h| h.time), Some(4));
+    assert_eq!(rdr.next_typed().unwrap().map(|m| time_of(&m)), Some(4));
+    assert!(rdr.peek().unwrap().is_none());
+}
+
+#[test]
+fn terminator_ends_the_stream_and_only_its_file_in_a_chain() {
+    // A lone terminator block, then data that must never be read.
+    let a = write_image("term-a", 4096, &[depths(&[1, 2]), vec![0; 12], depths(&[3]), [depths(&[4]), vec![0; 12]].concat()]);
+    let b = write_image("term-b", 4096, &[[depths(&[5]), vec![0; 12], depths(&[6])].concat(), depths(&[7])]);
+
+    let mut rdr = FastCacheReader::open(&a).unwrap();
+    let first = read_all(&mut rdr);
+    assert_eq!(first.len(), 2);
+    for _ in 0..3 { assert!(rdr.next_typed().unwrap().is_none(), "stays ended"); }
+    rdr.rewind().unwrap();
+    assert_eq!(read_all(&mut rdr), first);
+
+    let mut blocks = FastCacheReader::open(&a).unwrap().blocks();
+    let mut lens = Vec::new();
+    while let Some(b) = blocks.next_block().unwrap() { lens.push(b.bytes.len()); }
+    assert_eq!(lens, [58, 12]);
+
+    // In a chain the terminator ends its own file only.
+    let mut chain = MultiFileReader::open(&[&a, &b]).unwrap();
+    let mut got = Vec::new();
+    while let Some(m) = chain.next_typed().unwrap() { got.push(time_of(&m)); }
+    assert_eq!(got, [1, 2, 5]);
+}
+
+#[test]
+fn headerless_and_normal_framing_read_alike() {
+    let blocks = [depths(&[1, 2]), [tick(3, 1, 2, 3, MarketFlag::SELL), depths(&[4])].concat()];
+    let normal = write_image("framing-normal", 2048, &blocks);
+    let headerless = tmp("framing-headerless");
+    std::fs::write(&headerless, &image(2048, &blocks)[4..]).unwrap();
+
+    let rdr = FastCacheReader::open(&normal).unwrap();
+    assert_eq!(rdr.buffer_len(), 2048);
+    let want = drain(rdr);
+    assert_eq!(want.len(), 4);
+    let rdr = FastCacheReader::open_headerless(&headerless, 2048).unwrap();
+    assert_eq!(rdr.buffer_len(), 2048);
+    assert_eq!(drain(rdr), want);
+
+    // Read with the wrong framing, the first frame length is taken as the
+    // buffer length and the stream misparses.
+    let mut wrong = FastCacheReader::open(&headerless).unwrap();
+    assert!(wrong.next_typed().is_err());
+    assert!(FastCacheReader::open_headerless(&headerless, 0).is_err());
+}
+
+#[test]
+fn partial_final_block() {
+    // Fewer trailing bytes than a header: the block just ends.
+    let stub = [depths(&[3, 4]), depth(5, 1, 1, MarketFlag::BUY)[..11].to_vec()].concat();
+    let mut rdr = FastCacheReader::from_bytes(image(4096, &[depths(&[1, 2]), stub])).unwrap();
+    assert_eq!(read_all(&mut rdr).len(), 4);
+    assert!(rdr.next_typed().unwrap().is_none());
+
+    // A whole header whose size runs past the block: a PartialMessage.
+    let cut = [depths(&[3, 4]), depth(5, 1, 1, MarketFlag::BUY)[..20].to_vec()].concat();
+    let img = image(4096, &[depths(&[1, 2]), cut]);
+    let mut rdr = FastCacheReader::from_bytes(img.clone()).unwrap();
+    assert_eq!(times_until_error(&mut rdr), [1, 2, 3, 4]);
+    let mut rdr = FastCacheReader::from_bytes(img.clone()).unwrap();
+    for _ in 0..4 { rdr.next_typed().unwrap(); }
+    let err = rdr.next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::PartialMessage(p) if *p == PartialMessage { offset: 58, size: 29, available: 20 }), "{err}");
+    assert_eq!(err.code(), ERR_CORRUPT_MESSAGE);
+
+    // Lenient mode keeps what came before and records the rest.
+    let mut rdr = FastCacheReader::from_bytes(img).unwrap();
+    rdr.set_lenient(true);
+    assert_eq!(read_all(&mut rdr).len(), 4);
+    assert_eq!(rdr.errors().len(), 1);
+}
+
+#[test]
+fn lenient_mode_recovers_all_but_the_corrupt_block() {
+    let block = |b: i64| Some(depths(&[b * 10, b * 10 + 1]));
+    let img = image_with_corrupt(4096, &[block(1), block(2), None, block(4), block(5)]);
+
+    let mut rdr = FastCacheReader::from_bytes(img.clone()).unwrap();
+    rdr.set_lenient(true);
+    let got: Vec<_> = read_all(&mut rdr).iter().map(|m| i64::from_le_bytes(m[4..12].try_into().unwrap())).collect();
+    assert_eq!(got, [10, 11, 20, 21, 40, 41, 50, 51]);
+    assert_eq!(rdr.skipped_blocks(), 1);
+    assert_eq!(rdr.errors().len(), 1);
+
+    let mut strict = FastCacheReader::from_bytes(img).unwrap();
+    assert_eq!(times_until_error(&mut strict), [10, 11, 20, 21]);
+}
+
+/// `fields` as consecutive big‑endian values of the given byte widths.
+fn be(fields: &[(i64, usize)]) -> Vec<u8> {
+    fields.iter().flat_map(|&(v, w)| v.to_be_bytes()[8 - w..].to_vec()).collect()
+}
+
+#[test]
+fn reads_a_big_endian_file() {
+    let block = [
+        be(&[(0, 2), (29, 2), (1, 8), (-5, 8), (7, 8), (1, 1)]),
+        be(&[(1, 2), (37, 2), (2, 8), (9, 8), (10, 8), (11, 8), (2, 1)]),
+        [be(&[(2, 2), (16, 2), (3, 8)]), b"ESZ4".to_vec()].concat(),
+        be(&[(3, 2), (52, 2), (4, 8), (1, 8), (2, 8), (3, 8), (4, 8), (5, 8)]),
+        be(&[(4, 2), (12, 2), (5, 8)]),
+    ].concat();
+    let mut img = 4096i32.to_be_bytes().to_vec();
+    let p = k4os_pickler::pickle(&block).unwrap();
+    img.extend_from_slice(&(p.len() as i32).to_be_bytes());
+    img.extend_from_slice(&p);
+
+    let mut rdr = FastCacheReader::from_bytes(img.clone()).unwrap();
+    rdr.set_byte_order(Endian::Big).unwrap();
+    assert_eq!(rdr.buffer_len(), 4096);
+    let got = read_all(&mut rdr);
+    let want = [
+        Message::Depth(DepthItem::new(1, -5, 7, 1)),
+        Message::Tick(TickItem::new(2, 9, 10, 11, 2)),
+        Message::Symbol(SymbolItem { header: MessageHeader { kind: 2, size: 16, time: 3 }, name: "ESZ4".into() }),
+        Message::Candle(CandleItem::new(4, 1, 2, 3, 4, 5)),
+        Message::CandleEnd(MessageHeader { kind: 4, size: 12, time: 5 }),
+    ];
+    assert_eq!(got, want.iter().map(encode).collect::<Vec<_>>());
+    assert!(rdr.set_byte_order(Endian::Little).is_err(), "too late once a block is read");
+
+    // Read as little‑endian, the header is an absurd buffer length.
+    assert!(FastCacheReader::from_bytes(img).unwrap().next_typed().is_err());
+}
+
+#[test]
+fn compression_stats_match_the_fixture() {
+    let blocks = [depths(&[7; 40]), symbol(1, &[b'x'; 256]), depths(&[1, 2, 3])];
+    let pickled: Vec<_> = blocks.iter().map(|b| k4os_pickler::pickle(b).unwrap().len() as u64).collect();
+    let raw: Vec<_> = blocks.iter().map(|b| b.len() as u64).collect();
+    assert_eq!(raw, [1160, 268, 87]);
+    assert!(pickled[0] < raw[0] / 10, "{pickled:?}");
+
+    let mut rdr = FastCacheReader::from_bytes(image(4096, &blocks)).unwrap();
+    assert_eq!(rdr.compression_stats(), CompressionStats::default());
+    assert_eq!((rdr.compression_stats().ratio(), rdr.compression_stats().avg_block_len()), (None, None));
+    rdr.next_typed().unwrap();
+    assert_eq!(rdr.compression_stats(), CompressionStats { blocks: 1, compressed: pickled[0], decompressed: 1160 });
+    read_all(&mut rdr);
+
+    let stats = rdr.compression_stats();
+    let compressed: u64 = pickled.iter().sum();
+    assert_eq!(stats, CompressionStats { blocks: 3, compressed, decompressed: 1515 });
+    assert_eq!(stats.ratio(), Some(1515.0 / compressed as f64));
+    assert_eq!(stats.avg_block_len(), Some(505.0));
+}
+
+#[test]
+fn zstd_wrapped_file_is_named_on_open() {
+    // A zstd frame header: magic, then descriptor bytes the reader never
+    // gets to.
+    let zst = [0x28, 0xb5, 0x2f, 0xfd, 0x24, 0x10, 0x81, 0x00, 0x00];
+    let path = tmp("wrapped.bin.lz4.zst");
+    std::fs::write(&path, zst).unwrap();
+    for err in [FastCacheReader::open(&path).err().unwrap(), FastCacheReader::from_bytes(zst.to_vec()).err().unwrap()] {
+        assert!(err.to_string().contains("zstd"), "{err}");
+        assert_eq!(err.code(), ERR_CORRUPT_HEADER);
+    }
+}
+
+#[test]
+fn first_in_block_marks_exactly_each_block_start() {
+    let sizes = [3, 1, 4, 2];
+    let blocks: Vec<_> = sizes.iter().scan(0i64, |t, &n| { *t += n; Some(depths(&(*t - n..*t).collect::<Vec<_>>())) }).collect();
+    let blocks = [&blocks[..2], &[vec![0; 5]], &blocks[2..]].concat();    // a block with no messages in between
+    let mut msgs = FastCacheReader::from_bytes(image(4096, &blocks)).unwrap().messages();
+    let mut seen = Vec::new();
+    while let Some((_, at)) = msgs.next_with_block().unwrap() { seen.push((at.block_index, at.first_in_block)); }
+    let want: Vec<_> = [(0, 3), (1, 1), (3, 4), (4, 2)].iter()
+        .flat_map(|&(b, n)| (0..n).map(move |i| (b, i == 0)))
+        .collect();
+    assert_eq!(seen, want);
+}
@@ -0,0 +1,29 @@
+//! Worker shutdown, checked by counting the process's threads. Other
+//! tests in the same binary would skew the count, so this file has one.
+
+mod common;
+
+use common::*;
+use faststorage_native::*;
+
+#[cfg(target_os = "linux")]
+fn threads() -> usize { std::fs::read_dir("/proc/self/task").unwrap().count() }
+
+#[cfg(target_os = "linux")]
+#[test]
+fn dropping_par_blocks_mid_stream_joins_workers() {
+    let blocks: Vec<_> = (0..200).map(|b| depths(&(b * 50..b * 50 + 50).collect::<Vec<_>>())).collect();
+    let path = write_image("par-drop", 4096, &blocks);
+    let before = threads();
+
+    let mut it = FastCacheReader::open(&path).unwrap().par_blocks(4, 8);
+    for _ in 0..3 { assert!(it.next().unwrap().is_ok()); }
+    assert_eq!(threads(), before + 4);
+    drop(it);   // with jobs still queued and results unclaimed
+    assert_eq!(threads(), before);
+
+    // Draining to the end shuts down the same way.
+    let it = FastCacheReader::open(&path).unwrap().par_blocks(2, 4);
+    assert_eq!(it.map(|b| b.unwrap().messages().count()).sum::<usize>(), 200 * 50);
+    assert_eq!(threads(), before);
+}
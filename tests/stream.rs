@@ -0,0 +1,44 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+use std::io::{self, Read};
+
+/// Hands out at most three bytes per `read`, as a slow socket might, and
+/// doesn't implement `Seek`.
+struct Trickle(io::Cursor<Vec<u8>>);
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = buf.len().min(3);
+        self.0.read(&mut buf[..n])
+    }
+}
+
+fn trickle(bytes: &[u8]) -> FastCacheReader<Trickle> {
+    FastCacheReader::from_reader(Trickle(io::Cursor::new(bytes.to_vec()))).unwrap()
+}
+
+#[test]
+fn reads_from_a_stream_in_small_pieces() {
+    let blocks = [depths(&[1, 2]), tick(3, 1, 2, 3, MarketFlag::BUY), depths(&[4])];
+    let img = image(4096, &blocks);
+    assert_eq!(drain(trickle(&img)), drain(FastCacheReader::from_bytes(img.clone()).unwrap()));
+    assert_eq!(times(trickle(&img)), [1, 2, 3, 4]);
+}
+
+#[test]
+fn stream_ending_mid_block_is_an_error() {
+    let blocks = [depths(&[1, 2]), depths(&[3])];
+    let img = image(4096, &blocks);
+    let boundary = image(4096, &blocks[..1]).len();
+
+    // Ending on a block boundary is a clean end of stream.
+    assert_eq!(times(trickle(&img[..boundary])), [1, 2]);
+
+    // Anywhere inside the next frame, length prefix included, is not.
+    for cut in boundary + 1..img.len() {
+        let mut rdr = trickle(&img[..cut]);
+        assert_eq!(times_until_error(&mut rdr), [1, 2], "cut at {cut}");
+    }
+}
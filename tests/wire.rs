@@ -0,0 +1,180 @@
+//! No `unsafe` here: every field read goes through the by‑value getters.
+#![forbid(unsafe_code)]
+
+mod common;
+
+use common::*;
+use faststorage_native::*;
+
+const E8: i64 = 100_000_000;
+
+#[test]
+fn candle_block_built_by_hand_decodes() {
+    // kind, size, time, then open/high/low/close/volume, all little‑endian.
+    let mut candle = Vec::new();
+    candle.extend_from_slice(&3i16.to_le_bytes());
+    candle.extend_from_slice(&52u16.to_le_bytes());
+    for v in [1_000i64, 125 * E8 / 100, 2 * E8, E8 / 2, 175 * E8 / 100, 42 * E8] { candle.extend_from_slice(&v.to_le_bytes()); }
+    let mut end = 4i16.to_le_bytes().to_vec();
+    end.extend_from_slice(&12u16.to_le_bytes());
+    end.extend_from_slice(&1_060i64.to_le_bytes());
+
+    let mut rdr = FastCacheReader::from_bytes(image(4096, &[[candle, end].concat()])).unwrap();
+    let Some(Message::Candle(c)) = rdr.next_typed().unwrap() else { panic!("not a candle") };
+    assert_eq!({ c.header.time }, 1_000);
+    assert_eq!((c.open(), c.high(), c.low(), c.close(), c.volume()), (125_000_000, 200_000_000, 50_000_000, 175_000_000, 4_200_000_000));
+    let s = Scale::DEFAULT;
+    assert_eq!([s.price(c.open()), s.price(c.high()), s.price(c.low()), s.price(c.close()), s.volume(c.volume())], [1.25, 2.0, 0.5, 1.75, 42.0]);
+    assert_eq!(encode(&Message::Candle(c)), encode(&Message::Candle(CandleItem::new(1_000, 125 * E8 / 100, 2 * E8, E8 / 2, 175 * E8 / 100, 42 * E8))));
+    let Some(Message::CandleEnd(h)) = rdr.next_typed().unwrap() else { panic!("not a candle end") };
+    assert_eq!({ h.time }, 1_060);
+    assert!(rdr.next_typed().unwrap().is_none());
+}
+
+/// `msg` re‑encoded with `header.size` moved by `delta`, its payload
+/// padded or cut to match.
+fn resized(msg: &Message, delta: isize) -> Vec<u8> {
+    let mut out = encode(msg);
+    let size = (out.len() as isize + delta) as usize;
+    out.resize(size, 0);
+    out[2..4].copy_from_slice(&(size as u16).to_le_bytes());
+    out
+}
+
+fn typed(bytes: Vec<u8>, strict: bool) -> Result<Option<Message>> {
+    let mut rdr = FastCacheReader::from_bytes(image(4096, &[bytes])).unwrap();
+    rdr.set_strict_sizes(strict);
+    rdr.next_typed()
+}
+
+#[test]
+fn strict_sizes_check_each_kind() {
+    let end = MessageHeader { kind: MessageKind::CandleEnd as i16, size: 12, time: 5 };
+    let fixed = [
+        (MessageKind::Depth, Message::Depth(DepthItem::new(1, 2, 3, 0))),
+        (MessageKind::Tick, Message::Tick(TickItem::new(2, 1, 2, 3, 0))),
+        (MessageKind::Candle, Message::Candle(CandleItem::new(3, 1, 2, 3, 4, 5))),
+        (MessageKind::CandleEnd, Message::CandleEnd(end)),
+    ];
+    for (kind, msg) in &fixed {
+        let want = kind.wire_size().unwrap();
+        assert_eq!(encode(msg).len(), want);
+        assert!(typed(encode(msg), true).unwrap().is_some(), "{kind:?}");
+
+        // Longer than the struct: accepted unless strict, with the extra
+        // bytes ignored and `header.size` kept.
+        let m = typed(resized(msg, 8), false).unwrap().unwrap();
+        assert_eq!(encode(&m)[4..], encode(msg)[4..], "{kind:?}");
+        let err = typed(resized(msg, 8), true).unwrap_err();
+        assert!(matches!(err.root(), FastStorageError::CorruptMessage(m) if m.contains(&format!("{kind:?}")) && m.contains(&format!("expected {want}"))), "{err}");
+        assert_eq!(err.code(), ERR_CORRUPT_MESSAGE);
+
+        // Shorter: an error either way.
+        if *kind != MessageKind::CandleEnd {
+            assert!(typed(resized(msg, -1), false).is_err(), "{kind:?}");
+            assert!(typed(resized(msg, -1), true).is_err(), "{kind:?}");
+        }
+    }
+
+    // Symbols have no fixed size.
+    for name in [&b"E"[..], b"ESZ4", b"A_MUCH_LONGER_SYMBOL_NAME"] {
+        let Some(Message::Symbol(s)) = typed(symbol(1, name), true).unwrap() else { panic!("not a symbol") };
+        assert_eq!(s.name.as_bytes(), name);
+    }
+}
+
+#[test]
+fn kind_try_from_every_discriminant() {
+    use MessageKind::*;
+    for kind in [Depth, Tick, Symbol, Candle, CandleEnd] {
+        assert_eq!(MessageKind::try_from(kind as i16), Ok(kind));
+    }
+    for raw in [-1, 5, 99, i16::MIN, i16::MAX] {
+        assert_eq!(MessageKind::try_from(raw), Err(UnknownKind(raw)));
+    }
+    assert_eq!(UnknownKind(5).to_string(), "unknown message kind 5");
+
+    // The typed path reports the same error.
+    let mut unknown = encode(&Message::Depth(DepthItem::new(1, 2, 3, 0)));
+    unknown[..2].copy_from_slice(&7i16.to_le_bytes());
+    let err = FastCacheReader::from_bytes(image(4096, &[unknown])).unwrap().next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::UnknownKind(UnknownKind(7))), "{err}");
+}
+
+// Align 1, so a reference to one of these is never misaligned, wherever
+// it sits.
+const _: () = assert!(align_of::<DepthItem>() == 1 && align_of::<TickItem>() == 1 && align_of::<CandleItem>() == 1);
+
+// The size registry agrees with the structs, checked at compile time.
+const _: () = {
+    use MessageKind::*;
+    assert!(matches!(Depth.wire_size(), Some(n) if n == size_of::<DepthItem>() && n == 29));
+    assert!(matches!(Tick.wire_size(), Some(n) if n == size_of::<TickItem>() && n == 37));
+    assert!(matches!(Candle.wire_size(), Some(n) if n == size_of::<CandleItem>() && n == 52));
+    assert!(matches!(CandleEnd.wire_size(), Some(n) if n == size_of::<MessageHeader>() && n == 12));
+    assert!(Symbol.wire_size().is_none());
+};
+
+#[test]
+fn getters_read_structs_at_any_alignment() {
+    // Each struct one byte past an aligned address, inside a packed outer
+    // struct: `&outer.1` is fine, a reference to an `i64` field would not be.
+    #[repr(C, packed)]
+    struct Odd<T>(u8, T);
+    let d = Odd(0, DepthItem::new(1, -2, 3, 4));
+    let t = Odd(0, TickItem::new(5, 6, 7, 8, 9));
+    let c = Odd(0, CandleItem::new(10, 11, 12, 13, 14, 15));
+    assert_eq!((d.1.price(), d.1.volume(), d.1.flags()), (-2, 3, 4));
+    assert_eq!((t.1.id(), t.1.price(), t.1.volume(), t.1.raw_side()), (6, 7, 8, 9));
+    assert_eq!((c.1.open(), c.1.high(), c.1.low(), c.1.close(), c.1.volume()), (11, 12, 13, 14, 15));
+
+    // Messages at every offset of a byte buffer decode the same.
+    let msg = depth(1, -2, 3, MarketFlag::SELL);
+    for pad in 0..8 {
+        let buf = [vec![0; pad], msg.clone()].concat();
+        let d = BlockMessages::new(&buf[pad..]).next().unwrap().unwrap().as_depth().unwrap();
+        assert_eq!(({ d.header.time }, d.price(), d.volume(), d.flags()), (1, -2, 3, MarketFlag::SELL.bits()));
+    }
+}
+
+#[test]
+fn dotnet_ticks_to_unix_time() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let at = |time| MessageHeader { kind: 0, size: 29, time };
+    // DateTime(2000, 1, 1).Ticks and DateTime(2024, 1, 1).Ticks plus
+    // 0.1234567 s, both UTC; then the Unix epoch itself.
+    assert_eq!(at(630_822_816_000_000_000).to_unix_nanos(), Some(946_684_800_000_000_000));
+    assert_eq!(at(638_396_640_001_234_567).to_unix_nanos(), Some(1_704_067_200_123_456_700));
+    assert_eq!(at(DOTNET_UNIX_EPOCH_TICKS).to_unix_nanos(), Some(0));
+    assert_eq!(at(DOTNET_UNIX_EPOCH_TICKS - 1).to_unix_nanos(), Some(-100));
+    // DateTime.MinValue is out of range for i64 nanoseconds since 1970.
+    assert_eq!(at(0).to_unix_nanos(), None);
+
+    assert_eq!(at(638_396_640_001_234_567).to_system_time(), Some(UNIX_EPOCH + Duration::new(1_704_067_200, 123_456_700)));
+    assert_eq!(at(DOTNET_UNIX_EPOCH_TICKS - 10_000_000).to_system_time(), UNIX_EPOCH.checked_sub(Duration::from_secs(1)));
+}
+
+#[test]
+fn symbols_of_any_length_frame_by_header_size() {
+    let long: Vec<u8> = (0..300).map(|i| b'A' + (i % 26) as u8).collect();
+    let padded = [&b"CLZ4"[..], &[0; 12]].concat();
+    let img = image(4096, &[[symbol(1, b"X"), depths(&[2]), symbol(3, &long), symbol(4, &padded), depths(&[5])].concat()]);
+
+    let mut msgs = FastCacheReader::from_bytes(img.clone()).unwrap().messages();
+    let mut payloads = Vec::new();
+    while let Some(m) = msgs.next_message().unwrap() { payloads.push((m.bytes().len(), m.payload().to_vec())); }
+    assert_eq!(payloads.iter().map(|p| p.0).collect::<Vec<_>>(), [13, 29, 312, 28, 29]);
+    assert_eq!((&payloads[0].1[..], &payloads[2].1[..], &payloads[3].1[..]), (&b"X"[..], &long[..], &padded[..]));
+
+    let mut rdr = FastCacheReader::from_bytes(img).unwrap();
+    let mut names = Vec::new();
+    while let Some(m) = rdr.next_typed().unwrap() {
+        if let Message::Symbol(s) = m { names.push(s.name); }
+    }
+    assert_eq!(names, ["X".to_string(), String::from_utf8(long.clone()).unwrap(), "CLZ4".into()]);
+
+    // A name running past the block is caught by its header's size.
+    let cut = symbol(1, &long)[..200].to_vec();
+    let err = FastCacheReader::from_bytes(image(4096, &[cut])).unwrap().next_typed().unwrap_err();
+    assert!(matches!(err.root(), FastStorageError::PartialMessage(p) if p.size == 312 && p.available == 200), "{err}");
+}
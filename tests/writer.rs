@@ -0,0 +1,151 @@
+mod common;
+
+use common::*;
+use faststorage_native::*;
+
+/// A mix of kinds, enough to span several 512‑byte blocks.
+fn messages() -> Vec<Message> {
+    (0..200i64).map(|i| match i % 3 {
+        0 => Message::Tick(TickItem::new(i, i, 100 + i, 1, MarketFlag::SELL.bits())),
+        1 => Message::Depth(DepthItem::new(i, 100 + i % 7, i, MarketFlag::BUY.bits())),
+        _ => Message::Symbol(SymbolItem {
+            header: MessageHeader { kind: MessageKind::Symbol as i16, size: 18, time: i },
+            name:   format!("SYM{i:03}"),
+        }),
+    }).collect()
+}
+
+fn write_all(name: &str, checksums: bool, mode: CompressionMode) -> Vec<u8> {
+    let path = tmp(name);
+    let mut w = if checksums {
+        FastCacheWriter::create_checksummed(&path, 512, mode).unwrap()
+    } else {
+        FastCacheWriter::create(&path, 512, mode).unwrap()
+    };
+    for m in &messages() { w.write_typed(m).unwrap(); }
+    w.finish().unwrap();
+    std::fs::read(&path).unwrap()
+}
+
+#[test]
+fn same_messages_give_identical_files() {
+    for (checksums, mode) in [(false, CompressionMode::Lz4), (true, CompressionMode::Lz4), (false, CompressionMode::Stored)] {
+        let a = write_all(&format!("determinism-a-{checksums}-{mode:?}"), checksums, mode);
+        let b = write_all(&format!("determinism-b-{checksums}-{mode:?}"), checksums, mode);
+        assert_eq!(a, b, "checksums {checksums}, {mode:?}");
+        let mut rdr = FastCacheReader::from_bytes(a).unwrap();
+        let mut back = Vec::new();
+        while let Some(m) = rdr.next_typed().unwrap() { back.push(encode(&m)); }
+        assert_eq!(back, messages().iter().map(encode).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn append_continues_an_existing_file() {
+    let (all, path) = (messages(), tmp("append"));
+    let (head, tail) = all.split_at(120);
+
+    // Missing file: created with the given buffer length.
+    let mut w = FastCacheWriter::append(&path, 512, CompressionMode::Lz4).unwrap();
+    for m in head { w.write_typed(m).unwrap(); }
+    w.finish().unwrap();
+    let before = std::fs::read(&path).unwrap();
+
+    // Existing file: its header and blocks are kept, the new length ignored.
+    let mut w = FastCacheWriter::append(&path, 4096, CompressionMode::Lz4).unwrap();
+    for m in tail { w.write_typed(m).unwrap(); }
+    w.finish().unwrap();
+    let after = std::fs::read(&path).unwrap();
+    assert_eq!(after[..before.len()], before[..]);
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    assert_eq!(rdr.buffer_len(), 512);
+    let mut back = Vec::new();
+    while let Some(m) = rdr.next_typed().unwrap() { back.push(encode(&m)); }
+    assert_eq!(back, all.iter().map(encode).collect::<Vec<_>>());
+}
+
+#[test]
+fn follower_picks_up_appended_blocks() {
+    let path = tmp("follow");
+    let mut w = FastCacheWriter::create(&path, 4096, CompressionMode::Lz4).unwrap();
+    let dp = |t| Message::Depth(DepthItem::new(t, 100, 1, MarketFlag::BUY.bits()));
+    let next = |rdr: &mut FastCacheReader| {
+        let mut out = Vec::new();
+        while let Some(m) = rdr.next_typed().unwrap() { out.push(time_of(&m)); }
+        out
+    };
+    for t in [1, 2] { w.write_typed(&dp(t)).unwrap(); }
+    w.flush().unwrap();
+
+    let mut rdr = FastCacheReader::open(&path).unwrap();
+    rdr.set_follow(true);
+    assert_eq!(next(&mut rdr), [1, 2]);
+    assert_eq!(next(&mut rdr), []);
+
+    w.write_typed(&dp(3)).unwrap();
+    w.flush().unwrap();
+    assert_eq!(next(&mut rdr), [3]);
+
+    // A frame caught half written, in its length prefix or its payload,
+    // waits until the rest arrives.
+    w.finish().unwrap();
+    let frame = &image(4096, &[depths(&[4])])[4..];
+    let mut f = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    for part in [&frame[..2], &frame[2..9], &frame[9..]] {
+        assert_eq!(next(&mut rdr), []);
+        std::io::Write::write_all(&mut f, part).unwrap();
+    }
+    assert_eq!(next(&mut rdr), [4]);
+}
+
+#[test]
+fn append_drops_only_a_torn_final_frame() {
+    let blocks = [depths(&[1, 2]), depths(&[3]), depths(&[4])];
+    let whole = image(4096, &blocks);
+    let two = image(4096, &blocks[..2]).len();
+    let append_one = |path: &str| {
+        let mut w = FastCacheWriter::append(path, 4096, CompressionMode::Lz4).unwrap();
+        w.write_typed(&Message::Depth(DepthItem::new(9, 10_000_000_000, 100_000_000, MarketFlag::BUY.bits()))).unwrap();
+        w.finish().unwrap();
+        times(FastCacheReader::open(path).unwrap())
+    };
+
+    // Cut inside the last prefix, and inside the last payload.
+    for cut in [two + 2, whole.len() - 3] {
+        let path = tmp("append-torn");
+        std::fs::write(&path, &whole[..cut]).unwrap();
+        assert_eq!(append_one(&path), [1, 2, 3, 9], "cut at {cut}");
+    }
+
+    // A bad prefix with blocks after it: an error, and the file untouched.
+    let mut zeroed = whole.clone();
+    zeroed[two..two + 4].copy_from_slice(&0i32.to_le_bytes());
+    let mut negative = whole.clone();
+    negative[4..8].copy_from_slice(&(-7i32).to_le_bytes());
+    let mut huge = whole.clone();
+    huge[two..two + 4].copy_from_slice(&i32::MAX.to_le_bytes());
+    for (name, bytes) in [("zeroed", zeroed), ("negative", negative), ("huge", huge)] {
+        let path = tmp(&format!("append-{name}"));
+        std::fs::write(&path, &bytes).unwrap();
+        let err = FastCacheWriter::append(&path, 4096, CompressionMode::Lz4).err().unwrap();
+        assert!(matches!(err.root(), FastStorageError::CorruptBlock(m) if m.contains("invalid compressed length")), "{name}: {err}");
+        assert_eq!(std::fs::read(&path).unwrap(), bytes, "{name}");
+    }
+}
+
+#[test]
+fn append_checks_the_file_header() {
+    let zst = [0x28, 0xb5, 0x2f, 0xfd, 0x24, 0x10, 0x81, 0x00];
+    let mut big = image(4096, &[depths(&[1])]);
+    big[..4].copy_from_slice(&0x7fff_0000i32.to_le_bytes());
+    let mut zero = big.clone();
+    zero[..4].copy_from_slice(&0i32.to_le_bytes());
+    for (name, bytes, code) in [("zstd", zst.to_vec(), ERR_CORRUPT_HEADER), ("big", big, ERR_BLOCK_TOO_LARGE), ("zero", zero, ERR_CORRUPT_HEADER)] {
+        let path = tmp(&format!("append-header-{name}"));
+        std::fs::write(&path, &bytes).unwrap();
+        let err = FastCacheWriter::append(&path, 4096, CompressionMode::Lz4).err().unwrap();
+        assert_eq!(err.code(), code, "{name}: {err}");
+        assert_eq!(std::fs::read(&path).unwrap(), bytes, "{name}");
+    }
+}